@@ -0,0 +1,68 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+
+// The global config file is $GRIT_CONFIG_FILE, or "grit/config" in the user's config directory
+fn get_global_config_file() -> Option<String> {
+    env::var("GRIT_CONFIG_FILE")
+        .ok()
+        .or_else(|| {
+            env::var("XDG_CONFIG_HOME")
+                .ok()
+                .map(|x| format!("{x}/grit/config"))
+        })
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|x| format!("{x}/.config/grit/config"))
+        })
+}
+
+// Every "key = value" pair of the config file x, in the order they appear. Empty lines and lines
+// starting with `#` are skipped.
+fn read_entries(x: &str) -> Vec<(String, String)> {
+    fs::read_to_string(x)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .filter_map(|x| x.split_once('='))
+        .map(|x| (x.0.trim().to_string(), x.1.trim().to_string()))
+        .collect()
+}
+
+// The value of key x, the repository's .grit/config takes precedence over the global config file
+pub fn get(x: &str) -> Option<String> {
+    read_entries(".grit/config")
+        .into_iter()
+        .chain(
+            get_global_config_file()
+                .map(|x| read_entries(&x))
+                .unwrap_or_default(),
+        )
+        .zip(std::iter::repeat(x))
+        .find(|x| x.0 .0 == x.1)
+        .map(|x| x.0 .1)
+        .filter(|x| !x.is_empty())
+}
+
+// Sets key x.0 to x.1 in the repository's .grit/config, replacing the previous value if there is one
+pub fn set(x: (&str, &str)) {
+    let x: Vec<(String, String)> = read_entries(".grit/config")
+        .into_iter()
+        .zip(std::iter::repeat(x.0))
+        .filter(|x| x.0 .0 != x.1)
+        .map(|x| x.0)
+        .chain(std::iter::once((x.0.to_string(), x.1.to_string())))
+        .collect();
+
+    let _ = File::create(".grit/config")
+        .expect("Could not open config file")
+        .write_all(
+            x.iter()
+                .map(|x| format!("{} = {}\n", x.0, x.1))
+                .collect::<String>()
+                .as_bytes(),
+        );
+}
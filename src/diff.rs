@@ -0,0 +1,808 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+
+// A single step of turning the old lines into the new ones in the format (kind, old line, new
+// line), where the kind is ' ' for a line both sides have, '-' for a removed line and '+' for an
+// added line. The line numbers are where the step happens on each side (0-based), so a removed
+// line still says where it would have been in the new file and the other way around.
+pub type Edit = (char, usize, usize);
+
+// A range of edits in the format (first edit, one past the last edit)
+type EditRange = (usize, usize);
+
+// The lines of one version of a file, each with its line ending
+type Lines<'a> = &'a [&'a str];
+
+// A position on both sides in the format (old line, new line). Also used for a pair of lines that
+// are the same.
+type Point = (usize, usize);
+
+// The furthest point reached on each diagonal (old line minus new line) of the edit graph, shifted
+// by an offset so that the lowest diagonal is at index 0. The forward search stores the old line it
+// got to, the backward search how many old lines it got back from the end.
+type Frontier = Vec<usize>;
+
+// The state of a search for a point on a shortest path in the format (old lines, new lines, offset
+// of the frontiers, number of old lines minus number of new lines, forward frontier, backward
+// frontier)
+type Search<'a> = (
+    Lines<'a>,
+    Lines<'a>,
+    isize,
+    isize,
+    RefCell<Frontier>,
+    RefCell<Frontier>,
+);
+
+// How far along the diagonal starting at old line x.2 and new line x.3 both sides are the same
+fn follow_snake(x: (Lines, Lines, usize, usize)) -> usize {
+    x.0.get(x.2..)
+        .unwrap_or_default()
+        .iter()
+        .zip(x.1.get(x.3..).unwrap_or_default())
+        .take_while(|x| x.0 == x.1)
+        .count()
+}
+
+// How far back along the diagonal ending just before old line x.2 and new line x.3 both sides are
+// the same
+fn follow_snake_backward(x: (Lines, Lines, usize, usize)) -> usize {
+    x.0.get(..x.2)
+        .unwrap_or_default()
+        .iter()
+        .rev()
+        .zip(x.1.get(..x.3).unwrap_or_default().iter().rev())
+        .take_while(|x| x.0 == x.1)
+        .count()
+}
+
+// Where the furthest path to diagonal x.1 in step x.2 starts from, given the frontier x.0 of the
+// previous step with offset x.3: the end of the path on the diagonal above it, or one line further
+// than the end of the path on the diagonal below it
+fn get_diagonal_start(x: (&Frontier, isize, isize, isize)) -> usize {
+    match x.1 == -x.2
+        || (x.1 != x.2 && x.0[(x.1 - 1 + x.3) as usize] < x.0[(x.1 + 1 + x.3) as usize])
+    {
+        true => x.0[(x.1 + 1 + x.3) as usize],
+        false => x.0[(x.1 - 1 + x.3) as usize] + 1,
+    }
+}
+
+// Extends the forward search on diagonal x.0 in step x.1. If it runs into the backward search, the
+// point where the path joined the diagonal is on a shortest path and gets returned.
+fn extend_forward(x: (isize, isize, &Search)) -> Option<Point> {
+    let x: (isize, isize, usize, &Search) = (
+        x.0,
+        x.1,
+        get_diagonal_start((&x.2 .4.borrow(), x.0, x.1, x.2 .2)),
+        x.2,
+    );
+    let x: (isize, isize, Point, &Search) = (x.0, x.1, (x.2, (x.2 as isize - x.0) as usize), x.3);
+
+    x.3 .4.borrow_mut()[(x.0 + x.3 .2) as usize] =
+        x.2 .0 + follow_snake((x.3 .0, x.3 .1, x.2 .0, x.2 .1));
+
+    // The searches can only meet in a forward step if the difference in length is odd
+    Some(x.2).filter(|_| {
+        x.3 .3 % 2 != 0
+            && (x.0 - x.3 .3).abs() < x.1
+            && x.3 .4.borrow()[(x.0 + x.3 .2) as usize]
+                + x.3 .5.borrow()[(x.3 .3 - x.0 + x.3 .2) as usize]
+                >= x.3 .0.len()
+    })
+}
+
+// Extends the backward search on diagonal x.0 (of the sides read from their ends) in step x.1. If
+// it runs into the forward search, the point where the path left the diagonal is on a shortest
+// path and gets returned.
+fn extend_backward(x: (isize, isize, &Search)) -> Option<Point> {
+    let x: (isize, isize, usize, &Search) = (
+        x.0,
+        x.1,
+        get_diagonal_start((&x.2 .5.borrow(), x.0, x.1, x.2 .2)),
+        x.2,
+    );
+    let x: (isize, isize, usize, &Search) = (
+        x.0,
+        x.1,
+        x.2 + follow_snake_backward((
+            x.3 .0,
+            x.3 .1,
+            x.3 .0.len().saturating_sub(x.2),
+            x.3 .1.len().saturating_sub((x.2 as isize - x.0) as usize),
+        )),
+        x.3,
+    );
+
+    x.3 .5.borrow_mut()[(x.0 + x.3 .2) as usize] = x.2;
+
+    // The searches can only meet in a backward step if the difference in length is even
+    Some((
+        x.3 .0.len().saturating_sub(x.2),
+        x.3 .1.len().saturating_sub((x.2 as isize - x.0) as usize),
+    ))
+    .filter(|_| {
+        x.3 .3 % 2 == 0
+            && (x.0 - x.3 .3).abs() <= x.1
+            && x.2 + x.3 .4.borrow()[(x.3 .3 - x.0 + x.3 .2) as usize] >= x.3 .0.len()
+    })
+}
+
+// A point on a shortest path that turns the old lines x.0 into the new lines x.1, found by running
+// Myers' algorithm from both ends at once until the two searches meet. Only the latest frontier of
+// each search is kept, so this takes space linear in the number of lines.
+fn find_middle_snake(x: (Lines, Lines)) -> Option<Point> {
+    let x: Search = (
+        x.0,
+        x.1,
+        (x.0.len() + x.1.len()).div_ceil(2) as isize + 1,
+        x.0.len() as isize - x.1.len() as isize,
+        RefCell::new(vec![0; (x.0.len() + x.1.len()).div_ceil(2) * 2 + 3]),
+        RefCell::new(vec![0; (x.0.len() + x.1.len()).div_ceil(2) * 2 + 3]),
+    );
+
+    (0..x.2).zip(std::iter::repeat(&x)).find_map(|x| {
+        (-x.0..=x.0)
+            .step_by(2)
+            .zip(std::iter::repeat(x))
+            .find_map(|x| extend_forward((x.0, x.1 .0, x.1 .1)))
+            .or_else(|| {
+                (-x.0..=x.0)
+                    .step_by(2)
+                    .zip(std::iter::repeat(x))
+                    .find_map(|x| extend_backward((x.0, x.1 .0, x.1 .1)))
+            })
+    })
+}
+
+// The pairs of lines that stay the same in the shortest list of edits turning the old lines x.0
+// into the new lines x.1, whose first lines are at x.2. The lines are split at a point on a
+// shortest path and both halves are compared on their own. Every split about halves the number of
+// edits, so the recursion never gets deep.
+fn get_matches(x: (Lines, Lines, Point)) -> Vec<Point> {
+    // Lines both sides start or end with are matched right away
+    let x: (Lines, Lines, Point, usize) = (x.0, x.1, x.2, follow_snake((x.0, x.1, 0, 0)));
+    let x: (Lines, Lines, Point, usize, usize) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        follow_snake_backward((&x.0[x.3..], &x.1[x.3..], x.0.len() - x.3, x.1.len() - x.3)),
+    );
+
+    // Then come the lines in between, which start after the lines both sides start with
+    let x: (Lines, Lines, Point, usize, usize) = (
+        &x.0[x.3..x.0.len() - x.4],
+        &x.1[x.3..x.1.len() - x.4],
+        (x.2 .0 + x.3, x.2 .1 + x.3),
+        x.3,
+        x.4,
+    );
+
+    // Splitting at either end wouldn't make any progress
+    let x: (Lines, Lines, Point, usize, usize, Option<Point>) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        x.4,
+        match x.0.is_empty() || x.1.is_empty() {
+            true => None,
+            false => find_middle_snake((x.0, x.1))
+                .zip(Some((x.0.len(), x.1.len())))
+                .filter(|x| x.0 != (0, 0) && x.0 != x.1)
+                .map(|x| x.0),
+        },
+    );
+
+    [
+        (0..x.3)
+            .zip(std::iter::repeat((x.2 .0 - x.3, x.2 .1 - x.3)))
+            .map(|x| (x.1 .0 + x.0, x.1 .1 + x.0))
+            .collect::<Vec<Point>>(),
+        x.5.zip(Some((x.0, x.1, x.2)))
+            .map(|x| {
+                [
+                    get_matches((&x.1 .0[..x.0 .0], &x.1 .1[..x.0 .1], x.1 .2)),
+                    get_matches((
+                        &x.1 .0[x.0 .0..],
+                        &x.1 .1[x.0 .1..],
+                        (x.1 .2 .0 + x.0 .0, x.1 .2 .1 + x.0 .1),
+                    )),
+                ]
+                .concat()
+            })
+            .unwrap_or_default(),
+        (0..x.4)
+            .zip(std::iter::repeat((x.2 .0 + x.0.len(), x.2 .1 + x.1.len())))
+            .map(|x| (x.1 .0 + x.0, x.1 .1 + x.0))
+            .collect(),
+    ]
+    .concat()
+}
+
+// The shortest list of edits that turns the old lines x.0 into the new lines x.1. Between two
+// lines that stay the same, the removed lines come before the added ones.
+pub fn diff_lines(x: (Lines, Lines)) -> Vec<Edit> {
+    // Lines that only one of the sides has can never stay the same, so they're left out of the
+    // search. That makes comparing files that were rewritten entirely cheap.
+    let x: (Lines, Lines, HashSet<&str>, HashSet<&str>) = (
+        x.0,
+        x.1,
+        x.0.iter().copied().collect(),
+        x.1.iter().copied().collect(),
+    );
+    let x: (Lines, Lines, Vec<usize>, Vec<usize>) = (
+        x.0,
+        x.1,
+        (0..x.0.len())
+            .zip(std::iter::repeat(&x))
+            .filter(|x| x.1 .3.contains(x.1 .0[x.0]))
+            .map(|x| x.0)
+            .collect(),
+        (0..x.1.len())
+            .zip(std::iter::repeat(&x))
+            .filter(|x| x.1 .2.contains(x.1 .1[x.0]))
+            .map(|x| x.0)
+            .collect(),
+    );
+
+    // The matches found between the remaining lines, numbered like the original lines
+    let x: (usize, usize, Vec<Point>) = (
+        x.0.len(),
+        x.1.len(),
+        get_matches((
+            &x.2.iter()
+                .zip(std::iter::repeat(x.0))
+                .map(|x| x.1[*x.0])
+                .collect::<Vec<&str>>(),
+            &x.3.iter()
+                .zip(std::iter::repeat(x.1))
+                .map(|x| x.1[*x.0])
+                .collect::<Vec<&str>>(),
+            (0, 0),
+        ))
+        .into_iter()
+        .zip(std::iter::repeat((&x.2, &x.3)))
+        .map(|x| (x.1 .0[x.0 .0], x.1 .1[x.0 .1]))
+        .collect(),
+    );
+
+    // Every match ends a run of removed and added lines that starts right after the previous
+    // match. The last run ends at the end of both sides instead.
+    std::iter::once((0, 0))
+        .chain(x.2.iter().map(|x| (x.0 + 1, x.1 + 1)))
+        .zip(x.2.iter().copied().map(Some).chain(std::iter::once(None)))
+        .zip(std::iter::repeat((x.0, x.1)))
+        .flat_map(|x| {
+            let x: (Point, Point, bool) = (x.0 .0, x.0 .1.unwrap_or(x.1), x.0 .1.is_some());
+
+            (x.0 .0..x.1 .0)
+                .zip(std::iter::repeat(x.0 .1))
+                .map(|x| ('-', x.0, x.1))
+                .chain(
+                    (x.0 .1..x.1 .1)
+                        .zip(std::iter::repeat(x.1 .0))
+                        .map(|x| ('+', x.1, x.0)),
+                )
+                .chain(Some((' ', x.1 .0, x.1 .1)).filter(|_| x.2))
+                .collect::<Vec<Edit>>()
+        })
+        .collect()
+}
+
+// Groups the edits x.0 into hunks, each being the range of edits with changes and up to x.1 lines
+// of context around them. Changes that are close enough to share context end up in one hunk.
+fn get_hunks(x: (&[Edit], usize)) -> Vec<EditRange> {
+    // The ranges of edits from the first change to the last change of every hunk
+    let x: (&[Edit], usize, RefCell<Vec<EditRange>>) = (x.0, x.1, RefCell::new(Vec::new()));
+
+    x.0.iter()
+        .enumerate()
+        .filter(|x| x.1 .0 != ' ')
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| {
+            let x: (usize, bool, &RefCell<Vec<EditRange>>) = (
+                x.0 .0,
+                x.1 .2
+                    .borrow()
+                    .last()
+                    .zip(Some((x.0 .0, x.1 .1)))
+                    .is_some_and(|x| x.1 .0 <= x.0 .1 + 2 * x.1 .1),
+                &x.1 .2,
+            );
+
+            match x.1 {
+                true => {
+                    if let Some(x) = x.2.borrow_mut().last_mut().zip(Some(x.0)) {
+                        x.0 .1 = x.1 + 1;
+                    }
+                }
+                false => x.2.borrow_mut().push((x.0, x.0 + 1)),
+            }
+        });
+
+    x.2.into_inner()
+        .into_iter()
+        .zip(std::iter::repeat((x.0.len(), x.1)))
+        .map(|x| (x.0 .0.saturating_sub(x.1 .1), (x.0 .1 + x.1 .1).min(x.1 .0)))
+        .collect()
+}
+
+// The "start,count" of one side of a hunk header. An empty side starts at the line before it.
+fn format_range(x: (usize, usize)) -> String {
+    match x.1 {
+        0 => format!("{},0", x.0),
+        1 => format!("{}", x.0 + 1),
+        _ => format!("{},{}", x.0 + 1, x.1),
+    }
+}
+
+// Prints a single line of a hunk, noting when it's the last line of a file that doesn't end
+// with a newline
+fn format_line(x: (char, &str)) -> String {
+    match x.1.ends_with('\n') {
+        true => format!("{}{}", x.0, x.1),
+        false => format!("{}{}\n\\ No newline at end of file\n", x.0, x.1),
+    }
+}
+
+// The hunks of a unified diff between the old lines x.0 and the new lines x.1, with x.2 lines of
+// context around every change
+pub fn format_hunks(x: (&[&str], &[&str], usize)) -> String {
+    let x: (&[&str], &[&str], Vec<Edit>, usize) = (x.0, x.1, diff_lines((x.0, x.1)), x.2);
+    let x: (&[&str], &[&str], Vec<EditRange>, &[Edit]) = (x.0, x.1, get_hunks((&x.2, x.3)), &x.2);
+
+    x.2.iter()
+        .zip(std::iter::repeat((x.0, x.1, x.3)))
+        .map(|x| {
+            let x: (&[Edit], &[&str], &[&str]) = (&x.1 .2[x.0 .0..x.0 .1], x.1 .0, x.1 .1);
+
+            format!(
+                "@@ -{} +{} @@\n{}",
+                format_range((
+                    x.0.first().map_or(0, |x| x.1),
+                    x.0.iter().filter(|x| x.0 != '+').count()
+                )),
+                format_range((
+                    x.0.first().map_or(0, |x| x.2),
+                    x.0.iter().filter(|x| x.0 != '-').count()
+                )),
+                x.0.iter()
+                    .zip(std::iter::repeat((x.1, x.2)))
+                    .map(|x| match x.0 .0 {
+                        '+' => format_line(('+', x.1 .1[x.0 .2])),
+                        _ => format_line((x.0 .0, x.1 .0[x.0 .1])),
+                    })
+                    .collect::<String>()
+            )
+        })
+        .collect()
+}
+
+// The contents of a file on one side of a diff, None if the file doesn't exist on that side
+type Side<'a> = Option<&'a [u8]>;
+
+// A unified diff of file x.0 between the contents x.1 and x.2, with x.3 lines of context. Empty if
+// both sides are the same.
+pub fn format_diff(x: (&str, Side, Side, usize)) -> String {
+    if x.1 == x.2 {
+        return String::new();
+    }
+
+    let x: (&str, Side, Side, usize, String) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        format!(
+            "diff --grit a/{0} b/{0}\n{1}--- {2}\n+++ {3}\n",
+            x.0,
+            match (x.1, x.2) {
+                (None, _) => String::from("new file\n"),
+                (_, None) => String::from("deleted file\n"),
+                _ => String::new(),
+            },
+            x.1.map_or(String::from("/dev/null"), |_| format!("a/{}", x.0)),
+            x.2.map_or(String::from("/dev/null"), |_| format!("b/{}", x.0)),
+        ),
+    );
+
+    // Anything with a NUL byte in it is treated as binary, there's no meaningful line diff for it
+    if [x.1, x.2].iter().flatten().any(|x| x.contains(&0)) {
+        return format!("{}Binary files differ\n", x.4);
+    }
+
+    let x: (String, String, String, usize) = (
+        x.4,
+        String::from_utf8_lossy(x.1.unwrap_or_default()).to_string(),
+        String::from_utf8_lossy(x.2.unwrap_or_default()).to_string(),
+        x.3,
+    );
+
+    format!(
+        "{}{}",
+        x.0,
+        format_hunks((
+            &x.1.split_inclusive('\n').collect::<Vec<&str>>(),
+            &x.2.split_inclusive('\n').collect::<Vec<&str>>(),
+            x.3
+        ))
+    )
+}
+
+// A run of changes between two versions in the format (start and end of the old lines it replaces,
+// start and end of the new lines replacing them)
+type Change = (usize, usize, usize, usize);
+
+// Groups the edits x into runs of changes that aren't separated by lines both sides have
+fn get_changes(x: &[Edit]) -> Vec<Change> {
+    // The ranges of edits of every run
+    let x: (&[Edit], RefCell<Vec<EditRange>>) = (x, RefCell::new(Vec::new()));
+
+    x.0.iter()
+        .enumerate()
+        .filter(|x| x.1 .0 != ' ')
+        .zip(std::iter::repeat(&x.1))
+        .for_each(|x| {
+            // Whether the change directly follows the last run
+            let x: (usize, bool, &RefCell<Vec<EditRange>>) = (
+                x.0 .0,
+                x.1.borrow()
+                    .last()
+                    .zip(Some(x.0 .0))
+                    .is_some_and(|x| x.0 .1 == x.1),
+                x.1,
+            );
+
+            match x.1 {
+                true => {
+                    if let Some(x) = x.2.borrow_mut().last_mut() {
+                        x.1 += 1;
+                    }
+                }
+                false => x.2.borrow_mut().push((x.0, x.0 + 1)),
+            }
+        });
+
+    x.1.into_inner()
+        .into_iter()
+        .zip(std::iter::repeat(x.0))
+        .map(|x| {
+            (
+                x.1[x.0 .0].1,
+                x.1[x.0 .0].1 + x.1[x.0 .0..x.0 .1].iter().filter(|x| x.0 == '-').count(),
+                x.1[x.0 .0].2,
+                x.1[x.0 .0].2 + x.1[x.0 .0..x.0 .1].iter().filter(|x| x.0 == '+').count(),
+            )
+        })
+        .collect()
+}
+
+// The lines x.0 (one side of a merge) has in place of the old lines x.2..x.3 of the merge base
+// x.1, given the changes x.4 that side made within them
+fn get_side_lines<'a>(x: (&[&'a str], &[&'a str], usize, usize, &[Change])) -> Vec<&'a str> {
+    // The lines around the changes are the same as in the merge base
+    match x.4.first().zip(x.4.last()).zip(Some((x.0, x.2, x.3))) {
+        Some(x) => {
+            x.1 .0[x.0 .0 .2 - (x.0 .0 .0 - x.1 .1)..x.0 .1 .3 + (x.1 .2 - x.0 .1 .1)].to_vec()
+        }
+        None => x.1[x.2..x.3].to_vec(),
+    }
+}
+
+// A group of changes of both sides of a merge that overlap or touch each other, in the format
+// (start and end of the lines of the merge base the group replaces, the changes with whether they
+// come from the other side)
+type MergeGroup = (usize, usize, Vec<(Change, bool)>);
+
+// Groups the changes x of both sides of a merge (sorted by where they start in the merge base)
+fn get_merge_groups(x: BTreeSet<(Change, bool)>) -> Vec<MergeGroup> {
+    let x: (BTreeSet<(Change, bool)>, RefCell<Vec<MergeGroup>>) = (x, RefCell::new(Vec::new()));
+
+    x.0.into_iter().zip(std::iter::repeat(&x.1)).for_each(|x| {
+        // Whether the change starts before the end of the last group (or right at it)
+        let x: ((Change, bool), bool, &RefCell<Vec<MergeGroup>>) = (
+            x.0,
+            x.1.borrow()
+                .last()
+                .zip(Some(x.0 .0 .0))
+                .is_some_and(|x| x.1 <= x.0 .1),
+            x.1,
+        );
+
+        match x.1 {
+            true => {
+                if let Some(x) = x.2.borrow_mut().last_mut().zip(Some(x.0)) {
+                    x.0 .1 = x.0 .1.max(x.1 .0 .1);
+                    x.0 .2.push(x.1);
+                }
+            }
+            false => x.2.borrow_mut().push((x.0 .0 .0, x.0 .0 .1, vec![x.0])),
+        }
+    });
+
+    x.1.into_inner()
+}
+
+// Joins the lines x of one side of a conflict, making sure they end with a newline so that the
+// conflict marker after them is on a line of its own
+fn format_conflict_side(x: &[&str]) -> String {
+    match x.concat() {
+        x if x.is_empty() || x.ends_with('\n') => x,
+        x => format!("{x}\n"),
+    }
+}
+
+// Merges the changes that both sides x.1 ("ours") and x.2 ("theirs") made to the lines of their
+// merge base x.0. Where they changed the same lines differently, both versions are written out
+// between conflict markers labeled x.3 and x.4. Returns the merged text and whether there were any
+// conflicts.
+pub fn merge_lines(x: (Lines, Lines, Lines, &str, &str)) -> (String, bool) {
+    let x: (Lines, Lines, Lines, &str, &str, Vec<MergeGroup>) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        x.4,
+        get_merge_groups(
+            get_changes(&diff_lines((x.0, x.1)))
+                .into_iter()
+                .map(|x| (x, false))
+                .chain(
+                    get_changes(&diff_lines((x.0, x.2)))
+                        .into_iter()
+                        .map(|x| (x, true)),
+                )
+                .collect(),
+        ),
+    );
+
+    // Every group is preceded by the lines of the merge base since the group before it, which
+    // neither side changed
+    let x: Vec<(String, bool)> =
+        x.5.iter()
+            .zip(std::iter::once(0).chain(x.5.iter().map(|x| x.1)))
+            .zip(std::iter::repeat(&x))
+            .map(|x| {
+                let x: (&[&str], Vec<&str>, Vec<&str>, bool, bool, &str, &str) = (
+                    &x.1 .0[x.0 .1..x.0 .0 .0],
+                    get_side_lines((
+                        x.1 .1,
+                        x.1 .0,
+                        x.0 .0 .0,
+                        x.0 .0 .1,
+                        &x.0 .0
+                             .2
+                            .iter()
+                            .filter(|x| !x.1)
+                            .map(|x| x.0)
+                            .collect::<Vec<Change>>(),
+                    )),
+                    get_side_lines((
+                        x.1 .2,
+                        x.1 .0,
+                        x.0 .0 .0,
+                        x.0 .0 .1,
+                        &x.0 .0
+                             .2
+                            .iter()
+                            .filter(|x| x.1)
+                            .map(|x| x.0)
+                            .collect::<Vec<Change>>(),
+                    )),
+                    x.0 .0 .2.iter().any(|x| !x.1),
+                    x.0 .0 .2.iter().any(|x| x.1),
+                    x.1 .3,
+                    x.1 .4,
+                );
+
+                match x {
+                    x if !x.4 || x.1 == x.2 => (format!("{}{}", x.0.concat(), x.1.concat()), false),
+                    x if !x.3 => (format!("{}{}", x.0.concat(), x.2.concat()), false),
+                    x => (
+                        format!(
+                            "{}<<<<<<< {}\n{}=======\n{}>>>>>>> {}\n",
+                            x.0.concat(),
+                            x.5,
+                            format_conflict_side(&x.1),
+                            format_conflict_side(&x.2),
+                            x.6
+                        ),
+                        true,
+                    ),
+                }
+            })
+            .chain(std::iter::once((
+                x.0[x.5.last().map_or(0, |x| x.1)..].concat(),
+                false,
+            )))
+            .collect();
+
+    (
+        x.iter().map(|x| x.0.as_str()).collect(),
+        x.iter().any(|x| x.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The lines of x, each with its line ending
+    fn split(x: &str) -> Vec<&str> {
+        x.split_inclusive('\n').collect()
+    }
+
+    // The length of the longest common subsequence of x.0 and x.1, computed one old line at a time
+    // from the lengths for every prefix of x.1 against the old lines before it
+    fn get_common_length(x: (Lines, Lines)) -> usize {
+        let x: (Lines, Lines, RefCell<Vec<usize>>) =
+            (x.0, x.1, RefCell::new(vec![0; x.1.len() + 1]));
+
+        x.0.iter().zip(std::iter::repeat(&x)).for_each(|x| {
+            let x: (&str, Lines, Vec<usize>, &RefCell<Vec<usize>>) =
+                (x.0, x.1 .1, x.1 .2.borrow().clone(), &x.1 .2);
+
+            (0..x.1.len()).zip(std::iter::repeat(&x)).for_each(|x| {
+                x.1 .3.borrow_mut()[x.0 + 1] = match x.1 .1[x.0] == x.1 .0 {
+                    true => x.1 .2[x.0] + 1,
+                    false => x.1 .2[x.0 + 1].max(x.1 .3.borrow()[x.0]),
+                };
+            });
+        });
+
+        x.2.into_inner().last().copied().unwrap_or_default()
+    }
+
+    // The edits x.2 turn x.0 into x.1 and keep as many lines as possible
+    fn assert_shortest_edits(x: (Lines, Lines, &[Edit])) {
+        assert_eq!(
+            x.2.iter()
+                .filter(|x| x.0 != '+')
+                .map(|x| x.1)
+                .collect::<Vec<usize>>(),
+            (0..x.0.len()).collect::<Vec<usize>>()
+        );
+        assert_eq!(
+            x.2.iter()
+                .filter(|x| x.0 != '-')
+                .map(|x| x.2)
+                .collect::<Vec<usize>>(),
+            (0..x.1.len()).collect::<Vec<usize>>()
+        );
+        assert!(x
+            .2
+            .iter()
+            .filter(|x| x.0 == ' ')
+            .zip(std::iter::repeat((x.0, x.1)))
+            .all(|x| x.1 .0[x.0 .1] == x.1 .1[x.0 .2]));
+        assert_eq!(
+            x.2.iter().filter(|x| x.0 == ' ').count(),
+            get_common_length((x.0, x.1))
+        );
+    }
+
+    #[test]
+    fn diff_lines_handles_empty_and_identical_sides() {
+        assert_eq!(diff_lines((&[], &[])), Vec::<Edit>::new());
+        assert_eq!(
+            diff_lines((&split("a\nb\n"), &[])),
+            vec![('-', 0, 0), ('-', 1, 0)]
+        );
+        assert_eq!(
+            diff_lines((&[], &split("a\nb\n"))),
+            vec![('+', 0, 0), ('+', 0, 1)]
+        );
+        assert_eq!(
+            diff_lines((&split("a\nb\n"), &split("a\nb\n"))),
+            vec![(' ', 0, 0), (' ', 1, 1)]
+        );
+    }
+
+    // Removed lines come before the added ones between two lines that stay the same
+    #[test]
+    fn diff_lines_orders_removals_before_additions() {
+        assert_eq!(
+            diff_lines((&split("a\nb\nc\n"), &split("a\nx\nc\nd\n"))),
+            vec![
+                (' ', 0, 0),
+                ('-', 1, 1),
+                ('+', 2, 1),
+                (' ', 2, 2),
+                ('+', 3, 3)
+            ]
+        );
+        assert_eq!(
+            diff_lines((&split("a\nb\nc\n"), &split("c\n"))),
+            vec![('-', 0, 0), ('-', 1, 0), (' ', 2, 0)]
+        );
+    }
+
+    // The search from both ends finds a shortest edit list, whichever way the lengths differ
+    #[test]
+    fn diff_lines_finds_shortest_edits() {
+        let x: Vec<Vec<&str>> = std::iter::successors(Some(7u64), |x| {
+            Some(
+                x.wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407),
+            )
+        })
+        .map(|x| ["a\n", "b\n", "c\n", "d\n"][(x >> 33) as usize % 4])
+        .take(2000)
+        .collect::<Vec<&str>>()
+        .chunks(25)
+        .zip((0..).map(|x: usize| x % 25))
+        .map(|x| x.0[..x.1].to_vec())
+        .collect();
+
+        x.iter().zip(x.iter().skip(1)).for_each(|x| {
+            assert_shortest_edits((x.0, x.1, &diff_lines((x.0, x.1))));
+            assert_shortest_edits((x.1, x.0, &diff_lines((x.1, x.0))));
+        });
+    }
+
+    #[test]
+    fn format_hunks_notes_missing_newline() {
+        assert_eq!(
+            format_hunks((&split("a\nb"), &split("a\nc"), 3)),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n"
+        );
+    }
+
+    // Changes of one side, or the same change on both sides, merge cleanly
+    #[test]
+    fn merge_lines_merges_separate_changes() {
+        assert_eq!(
+            merge_lines((
+                &split("a\nb\nc\nd\ne\n"),
+                &split("A\nb\nc\nd\ne\n"),
+                &split("a\nb\nc\nd\nE\n"),
+                "ours",
+                "theirs"
+            )),
+            (String::from("A\nb\nc\nd\nE\n"), false)
+        );
+        assert_eq!(
+            merge_lines((
+                &split("a\nb\n"),
+                &split("a\nx\n"),
+                &split("a\nx\n"),
+                "ours",
+                "theirs"
+            )),
+            (String::from("a\nx\n"), false)
+        );
+        assert_eq!(
+            merge_lines((&split("a\n"), &split("a\n"), &[], "ours", "theirs")),
+            (String::new(), false)
+        );
+    }
+
+    // Different changes to the same lines are written out between conflict markers
+    #[test]
+    fn merge_lines_marks_conflicts() {
+        assert_eq!(
+            merge_lines((
+                &split("a\nb\nc\n"),
+                &split("a\nx\nc\n"),
+                &split("a\ny\nc\n"),
+                "ours",
+                "theirs"
+            )),
+            (
+                String::from("a\n<<<<<<< ours\nx\n=======\ny\n>>>>>>> theirs\nc\n"),
+                true
+            )
+        );
+
+        // A side without a final newline still gets the marker after it on a line of its own
+        assert_eq!(
+            merge_lines((&split("a"), &split("b"), &split("c"), "ours", "theirs")),
+            (
+                String::from("<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n"),
+                true
+            )
+        );
+    }
+}
@@ -1,144 +1,2079 @@
+use chrono::{DateTime, FixedOffset, Local, TimeZone};
+use flate2::read::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
 use sha1::{Digest, Sha1};
+use std::env;
 use std::fs;
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
+use std::str::FromStr;
 
-pub fn hash_string(s: &String) -> String {
-    Sha1::digest(s.as_bytes())
-        .iter()
-        .map(|&x| format!("{:x}", x))
+use crate::config;
+use crate::ignore;
+
+// The full 20-byte SHA-1 digest of an object. Always printed as exactly 40 lowercase hex
+// characters, so every id has one canonical spelling.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ObjectId([u8; 20]);
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|x| write!(f, "{:02x}", x))
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = String;
+
+    fn from_str(x: &str) -> Result<Self, Self::Err> {
+        // Only lowercase is canonical, so an uppercase id would never match an object file
+        if x.len() != 40 || !x.bytes().all(|x| matches!(x, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err(format!("{x} is not a valid object id"));
+        }
+
+        // Every pair of hex characters is one byte of the digest
+        let x: Vec<u8> = x
+            .as_bytes()
+            .chunks(2)
+            .map(|x| {
+                u8::from_str_radix(std::str::from_utf8(x).unwrap_or_default(), 16).unwrap_or(0)
+            })
+            .collect();
+
+        Ok(ObjectId(x.try_into().expect("Digest is always 20 bytes")))
+    }
+}
+
+// Mapping from the ids an object was previously stored under to its canonical id
+pub type IdMapping = RefCell<HashMap<String, ObjectId>>;
+
+pub fn hash_bytes(x: &[u8]) -> ObjectId {
+    ObjectId(Sha1::digest(x).into())
+}
+
+// The index maps every staged filepath to the hash of the contents it was staged with, or to None
+// if the removal of the file is staged
+pub type Index = BTreeMap<String, Staged>;
+
+// What the index stages for a single filepath: the hash of its contents, or None for a removal
+pub type Staged = Option<ObjectId>;
+
+// The staged changes of the index, with hashes that aren't valid object ids left out
+pub fn get_index() -> Index {
+    get_raw_index()
+        .into_iter()
+        .filter_map(|x| Some((x.0, x.1.map(|x| x.parse()).transpose().ok()?)))
         .collect()
 }
 
-pub fn get_index() -> Vec<String> {
+// The index as it's stored, mapping every staged filepath to the hash it was staged with (None for
+// a removal) without checking that the hashes are valid ids. Only repositories that still have to
+// be migrated with `grit migrate-ids` have anything else in there.
+pub type RawIndex = BTreeMap<String, Option<String>>;
+
+// Each line of the index is formatted like a tree entry, "blob\t<hash>\t<filepath>" for staged
+// contents and "delete\t\t<filepath>" for staged removals
+pub fn get_raw_index() -> RawIndex {
     // Create the index file if it doesn't already exist
     let x = ".grit/index";
     if !Path::new(x).exists() {
         let _ = File::create(x);
     }
 
-    // Generate vector of filenames (removing possible empty lines)
+    // Generate map of filenames (removing possible empty lines)
     let x: String = fs::read_to_string(x).expect("Could not read index file");
-    let x: Vec<String> = x.split("\n").map(|x| x.to_string()).collect();
-    x.into_iter().filter(|x| x.len() > 0).collect()
+    x.split("\n")
+        .filter(|x| !x.is_empty() && !x.starts_with("conflict\t"))
+        .map(|x| x.splitn(3, '\t').collect::<Vec<&str>>())
+        .filter_map(|x| match x[..] {
+            ["delete", _, _] => Some((x[2].to_string(), None)),
+            [_, _, _] => Some((x[2].to_string(), Some(x[1].to_string()))),
+            // Indexes written before they held hashes only contain the filepath, in which case the
+            // file is staged as it currently is
+            _ => Some((x[0].to_string(), Some(hash_blob(&fs::read(x[0]).ok()?)))),
+        })
+        .collect()
+}
+
+// The versions of a path that a merge couldn't combine in the format (merge base, ours, theirs),
+// None where the path doesn't exist in that version
+pub type Conflict = (Option<String>, Option<String>, Option<String>);
+
+// Conflicts are kept in the index as "conflict\t<base> <ours> <theirs>\t<filepath>" lines (with "-"
+// for a version that doesn't exist) until the path is staged again
+pub fn get_conflicts() -> BTreeMap<String, Conflict> {
+    fs::read_to_string(".grit/index")
+        .unwrap_or_default()
+        .split("\n")
+        .filter_map(|x| x.strip_prefix("conflict\t"))
+        .filter_map(|x| x.split_once('\t'))
+        .map(|x| {
+            let x: (Vec<Option<String>>, &str) = (
+                x.0.split(' ')
+                    .map(|x| Some(x.to_string()).filter(|x| x != "-"))
+                    .collect(),
+                x.1,
+            );
+
+            (
+                x.1.to_string(),
+                (
+                    x.0.first().cloned().flatten(),
+                    x.0.get(1).cloned().flatten(),
+                    x.0.get(2).cloned().flatten(),
+                ),
+            )
+        })
+        .collect()
+}
+
+// Writes the staged changes x.0 and the conflicts x.1 to the index
+fn write_index_entries(x: (&Index, &BTreeMap<String, Conflict>)) {
+    let x: String =
+        x.0.iter()
+            .map(|x| match x.1 {
+                Some(_) => format!(
+                    "blob\t{}\t{}",
+                    x.1.map(|x| x.to_string()).unwrap_or_default(),
+                    x.0
+                ),
+                None => format!("delete\t\t{}", x.0),
+            })
+            .chain(x.1.iter().map(|x| {
+                format!(
+                    "conflict\t{} {} {}\t{}",
+                    x.1 .0.as_deref().unwrap_or("-"),
+                    x.1 .1.as_deref().unwrap_or("-"),
+                    x.1 .2.as_deref().unwrap_or("-"),
+                    x.0
+                )
+            }))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+    let _ = write!(
+        File::create(".grit/index").expect("Could not open index file"),
+        "{}",
+        x
+    );
+}
+
+// Writes the staged changes x to the index. Staging a path resolves its conflict, conflicts of
+// other paths are kept.
+pub fn write_index(x: &Index) {
+    write_index_entries((
+        x,
+        &get_conflicts()
+            .into_iter()
+            .zip(std::iter::repeat(x))
+            .filter(|x| !x.1.contains_key(&x.0 .0))
+            .map(|x| x.0)
+            .collect(),
+    ));
+}
+
+// Replaces the conflicts in the index with x, keeping the staged changes
+pub fn write_conflicts(x: &BTreeMap<String, Conflict>) {
+    write_index_entries((&get_index(), x));
+}
+
+// The commit being merged into HEAD while a merge waits for its conflicts to be resolved
+pub fn get_merge_head() -> Option<String> {
+    fs::read_to_string(".grit/MERGE_HEAD")
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+}
+
+// The status of the path x.0 given the HEAD tree (x.1), the index (x.2) and the tree the next
+// commit would have (x.3), as a pair of characters like in `git status --short`. The first one
+// is the change staged in the index and the second one the change in the working tree that
+// hasn't been staged: 'A'dded, 'M'odified, 'D'eleted or ' ' for no change. Files that aren't
+// tracked at all are "??".
+// A file whose removal is staged while it still exists in the working tree (e.g. after
+// `grit rm --cached`) is both deleted and untracked, so every path gets a list of statuses.
+fn get_change(
+    x: (
+        &String,
+        &HashMap<String, String>,
+        &Index,
+        &HashMap<String, String>,
+    ),
+) -> Vec<(char, char)> {
+    let x: (
+        Option<&String>,
+        Option<&Staged>,
+        Option<&String>,
+        Option<String>,
+    ) = (
+        x.1.get(x.0),
+        x.2.get(x.0),
+        x.3.get(x.0),
+        fs::read(x.0).ok().map(|x| hash_blob(&x)),
+    );
+
+    match (x.1, x.2) {
+        (Some(None), _) if x.3.is_some() => vec![('D', ' '), ('?', '?')],
+        (Some(None), _) => vec![('D', ' ')],
+        (_, None) => vec![('?', '?')],
+        _ => vec![(
+            match x.1 {
+                None => ' ',
+                Some(_) if x.0.is_none() => 'A',
+                Some(_) if x.1.cloned().flatten().map(|x| x.to_string()).as_ref() == x.0 => ' ',
+                _ => 'M',
+            },
+            match x.3 {
+                None => 'D',
+                _ if x.3.as_ref() == x.2 => ' ',
+                _ => 'M',
+            },
+        )],
+    }
+}
+
+// The tree of the commit HEAD points to
+pub fn get_head_tree() -> HashMap<String, String> {
+    get_tree(get_tree_of_commit(get_current_head().map(|x| x.to_string()).as_ref()).as_ref())
+}
+
+// The tree the next commit would have, i.e. the tree x.0 with the changes staged in index x.1
+// applied on top of it
+pub fn apply_index(x: (HashMap<String, String>, &Index)) -> HashMap<String, String> {
+    x.0.into_iter()
+        .zip(std::iter::repeat(x.1))
+        .filter(|x| !x.1.contains_key(&x.0 .0))
+        .map(|x| x.0)
+        .chain(
+            x.1.iter()
+                .filter_map(|x| Some((x.0.clone(), x.1.as_ref()?.to_string()))),
+        )
+        .collect()
+}
+
+// A path along with its status (see get_change)
+pub type PathStatus = (String, (char, char));
+
+// Compares the HEAD tree, the index and the working tree, giving the status of every path that
+// differs between them (see get_change)
+pub fn get_changes() -> Vec<PathStatus> {
+    let x: (HashMap<String, String>, Index) = (get_head_tree(), get_index());
+    let x: (HashMap<String, String>, Index, HashMap<String, String>) =
+        (x.0.clone(), x.1.clone(), apply_index((x.0, &x.1)));
+
+    // Tracked and staged files are always compared (even if they're ignored), other files only
+    // when they aren't ignored
+    let x: (Vec<PathStatus>, Tree, Index) = (
+        x.0.keys()
+            .chain(x.1.keys())
+            .cloned()
+            .chain(get_working_tree_files(""))
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .zip(std::iter::repeat(&x))
+            .flat_map(|x| {
+                get_change((&x.0, &x.1 .0, &x.1 .1, &x.1 .2))
+                    .into_iter()
+                    .zip(std::iter::repeat(x.0))
+            })
+            .filter(|x| x.0 != (' ', ' '))
+            .map(|x| (x.1, x.0))
+            .collect(),
+        x.0,
+        x.1,
+    );
+
+    // Conflicted paths are only reported as such, with which sides changed them: 'U'pdated,
+    // 'A'dded or 'D'eleted
+    let x: (Vec<PathStatus>, BTreeMap<String, Conflict>) =
+        (find_renames((x.0, &x.1, &x.2)), get_conflicts());
+
+    x.0.into_iter()
+        .zip(std::iter::repeat(&x.1))
+        .filter(|x| !x.1.contains_key(&x.0 .0))
+        .map(|x| x.0)
+        .chain(x.1.iter().map(|x| {
+            (
+                x.0.clone(),
+                match x.1 {
+                    (None, Some(_), Some(_)) => ('A', 'A'),
+                    (_, None, _) => ('D', 'U'),
+                    (_, _, None) => ('U', 'D'),
+                    _ => ('U', 'U'),
+                },
+            )
+        }))
+        .collect::<BTreeSet<PathStatus>>()
+        .into_iter()
+        .collect()
+}
+
+// Staged removals that a rename can still claim, in the format (path, hash it had in HEAD)
+type Removals = RefCell<Vec<(String, String)>>;
+
+// Takes the first removal in x.0 whose hash was x.1, giving the path that was removed
+fn claim_removal(x: (&Removals, Option<String>)) -> Option<String> {
+    let x: (&Removals, Option<usize>) = (
+        x.0,
+        x.0.borrow()
+            .iter()
+            .zip(std::iter::repeat(&x.1))
+            .position(|x| Some(&x.0 .1) == x.1.as_ref()),
+    );
+
+    Some(x.0.borrow_mut().remove(x.1?).0)
+}
+
+// Pairs up staged additions with staged removals of the exact same contents, given the HEAD tree
+// x.1 and the index x.2. Each pair is turned into a single 'R'enamed change of the path
+// "<old path> -> <new path>".
+fn find_renames(x: (Vec<PathStatus>, &Tree, &Index)) -> Vec<PathStatus> {
+    // Every removed path along with the hash it had in HEAD
+    let x: (Vec<PathStatus>, &Index, Removals) = (
+        x.0.clone(),
+        x.2,
+        RefCell::new(
+            x.0.iter()
+                .filter(|x| x.1 .0 == 'D')
+                .zip(std::iter::repeat(x.1))
+                .filter_map(|x| Some((x.0 .0.clone(), x.1.get(&x.0 .0)?.clone())))
+                .collect(),
+        ),
+    );
+
+    // Additions claim the first removal with the same hash as their old path
+    let x: (Vec<PathStatus>, Removals) = (
+        x.0.iter()
+            .zip(std::iter::repeat((x.1, &x.2)))
+            .map(|x| match x.0 .1 .0 {
+                'A' => claim_removal((
+                    x.1 .1,
+                    x.1 .0
+                        .get(&x.0 .0)
+                        .cloned()
+                        .flatten()
+                        .map(|x| x.to_string()),
+                ))
+                .zip(Some(x.0))
+                .map_or(x.0.clone(), |x| {
+                    (format!("{} -> {}", x.0, x.1 .0), ('R', x.1 .1 .1))
+                }),
+                _ => x.0.clone(),
+            })
+            .collect(),
+        x.2,
+    );
+
+    // Removals that were claimed by a rename aren't reported on their own
+    x.0.iter()
+        .zip(std::iter::repeat(&x.1))
+        .filter(|x| {
+            x.0 .1 .0 != 'D'
+                || x.1
+                    .borrow()
+                    .iter()
+                    .zip(std::iter::repeat(&x.0 .0))
+                    .any(|x| x.0 .0 == *x.1)
+        })
+        .map(|x| x.0.clone())
+        .collect()
+}
+
+// Joins a path onto a directory, where the root of the repository is the empty string
+pub fn join_path(x: (&str, &str)) -> String {
+    match x.0.is_empty() {
+        true => x.1.to_string(),
+        false => format!("{}/{}", x.0, x.1),
+    }
+}
+
+// Turns a path given by the user into the form it's tracked in, e.g. "./src//main.rs/" into
+// "src/main.rs". The root of the repository becomes the empty string.
+pub fn normalize_path(x: &str) -> String {
+    x.split('/')
+        .filter(|x| !x.is_empty() && *x != ".")
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+// Every file below the directory x (recursively, skipping the .grit directory and anything that is
+// ignored), as paths relative to the root of the repository in sorted order
+pub fn get_working_tree_files(x: &str) -> Vec<String> {
+    walk_working_tree((x, Some(&ignore::get_rules(x))))
+}
+
+// Like get_working_tree_files, but ignored files are included as well
+pub fn get_all_working_tree_files(x: &str) -> Vec<String> {
+    walk_working_tree((x, None))
+}
+
+// Walks the directory x.0 with the ignore rules x.1 that apply to it, or without leaving anything
+// out if there are none. The rules of every subdirectory are built from those of its parent, so
+// each ignore file is only read once.
+fn walk_working_tree(x: (&str, Option<&[ignore::Rule]>)) -> Vec<String> {
+    let x: (&str, Option<&[ignore::Rule]>, Vec<fs::DirEntry>) = (
+        x.0,
+        x.1,
+        fs::read_dir(if x.0.is_empty() { "." } else { x.0 })
+            .map(|x| x.filter_map(|x| x.ok()).collect())
+            .unwrap_or_default(),
+    );
+
+    let x: (Option<&[ignore::Rule]>, BTreeMap<String, bool>) = (
+        x.1,
+        x.2.iter()
+            .zip(std::iter::repeat((x.0, x.1)))
+            .map(|x| {
+                (
+                    join_path((x.1 .0, &x.0.file_name().to_string_lossy())),
+                    x.0.file_type().is_ok_and(|x| x.is_dir()),
+                    x.1 .1,
+                )
+            })
+            .filter(|x| {
+                x.0 != ".grit"
+                    && !x
+                        .2
+                        .zip(Some((&x.0, x.1)))
+                        .is_some_and(|x| ignore::is_excluded((x.0, x.1 .0, x.1 .1)))
+            })
+            .map(|x| (x.0, x.1))
+            .collect(),
+    );
+
+    x.1.into_iter()
+        .zip(std::iter::repeat(x.0))
+        .flat_map(|x| match x.0 .1 {
+            true => walk_working_tree((
+                &x.0 .0,
+                x.1.zip(Some(&x.0 .0))
+                    .map(|x| ignore::add_directory_rules((x.0, x.1)))
+                    .as_deref(),
+            )),
+            false => vec![x.0 .0],
+        })
+        .collect()
+}
+
+// Checks whether the character x.1 is in the set of a bracket expression like "a-z_" (without
+// the brackets)
+fn class_contains(x: (&[u8], u8)) -> bool {
+    match x.0 {
+        [] => false,
+        [_, b'-', _, ..] => (x.0[0]..=x.0[2]).contains(&x.1) || class_contains((&x.0[3..], x.1)),
+        [_, ..] => x.0[0] == x.1 || class_contains((&x.0[1..], x.1)),
+    }
+}
+
+// Matches the character x.1 against the bracket expression at the start of pattern x.0, giving
+// whether it matched and the length of the whole expression. None if the bracket is never closed.
+fn match_class(x: (&[u8], u8)) -> Option<(bool, usize)> {
+    // Negated sets start with `!` or `^`
+    let x: (&[u8], u8, bool) = (x.0, x.1, matches!(x.0.get(1), Some(b'!') | Some(b'^')));
+    let x: (&[u8], u8, bool, usize) = (x.0, x.1, x.2, if x.2 { 2 } else { 1 });
+
+    // A `]` right at the start of the set is a literal character rather than the end of it
+    let x: (&[u8], u8, bool, usize, usize) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        x.3 + 1 + x.0.get(x.3 + 1..)?.iter().position(|x| *x == b']')?,
+    );
+
+    Some((class_contains((&x.0[x.3..x.4], x.1)) != x.2, x.4 + 1))
+}
+
+// Matches the path x.1 against the glob pattern x.0. `*`, `?` and `[...]` never match a `/`, while
+// `**` matches across directories (and `**/` matches zero or more whole directories).
+pub fn glob_match(x: (&[u8], &[u8])) -> bool {
+    match x.0 {
+        [] => x.1.is_empty(),
+        [b'*', b'*', b'/', ..] => {
+            glob_match((&x.0[3..], x.1))
+                || x.1
+                    .splitn(2, |x| *x == b'/')
+                    .nth(1)
+                    .zip(Some(x.0))
+                    .is_some_and(|x| glob_match((x.1, x.0)))
+        }
+        [b'*', b'*', ..] => {
+            glob_match((&x.0[2..], x.1)) || (!x.1.is_empty() && glob_match((x.0, &x.1[1..])))
+        }
+        [b'*', ..] => {
+            glob_match((&x.0[1..], x.1))
+                || (x.1.first().is_some_and(|x| *x != b'/') && glob_match((x.0, &x.1[1..])))
+        }
+        [b'?', ..] => x.1.first().is_some_and(|x| *x != b'/') && glob_match((&x.0[1..], &x.1[1..])),
+        [b'[', ..] if x.1.first().is_some_and(|x| *x != b'/') => {
+            let x: (&[u8], &[u8], Option<(bool, usize)>) = (x.0, x.1, match_class((x.0, x.1[0])));
+
+            match x.2 {
+                Some(_) => {
+                    x.2.is_some_and(|x| x.0)
+                        && glob_match((&x.0[x.2.map_or(0, |x| x.1)..], &x.1[1..]))
+                }
+                // Without a closing bracket the `[` is just a regular character
+                None => x.1[0] == b'[' && glob_match((&x.0[1..], &x.1[1..])),
+            }
+        }
+        [b'\\', _, ..] => x.1.first() == x.0.get(1) && glob_match((&x.0[2..], &x.1[1..])),
+        [_, ..] => x.1.first() == x.0.first() && glob_match((&x.0[1..], &x.1[1..])),
+    }
+}
+
+pub fn is_glob(x: &str) -> bool {
+    x.contains(['*', '?', '['])
+}
+
+// The directory every path matched by pathspec x is in (or the path itself if it's not a glob),
+// empty if it can match paths anywhere. Patterns without a `/` match files in any directory,
+// other globs can only match below the directories they start with.
+pub fn get_pathspec_directory(x: &str) -> String {
+    let x: (String, bool) = (normalize_path(x), is_glob(x));
+
+    match x.1 {
+        true if x.0.contains('/') => {
+            x.0.split('/')
+                .take_while(|x| !is_glob(x))
+                .collect::<Vec<&str>>()
+                .join("/")
+        }
+        true => String::new(),
+        false => x.0,
+    }
+}
+
+// Checks whether the path x.1 is matched by the pathspec x.0, which is either a glob pattern, a
+// directory containing the path or the path itself. Patterns without a `/` are matched against the
+// name of the file in any directory.
+pub fn matches_pathspec(x: (&str, &str)) -> bool {
+    let x: (String, bool, &str) = (normalize_path(x.0), is_glob(x.0), x.1);
+
+    match x.1 {
+        true if x.0.contains('/') => glob_match((x.0.as_bytes(), x.2.as_bytes())),
+        true => glob_match((
+            x.0.as_bytes(),
+            x.2.rsplit('/').next().unwrap_or_default().as_bytes(),
+        )),
+        false => {
+            x.0.is_empty()
+                || x.2 == x.0
+                || x.2
+                    .strip_prefix(x.0.as_str())
+                    .is_some_and(|x| x.starts_with('/'))
+        }
+    }
+}
+
+// Expands a pathspec (a file, a directory or a glob pattern) into the files of the working tree
+// it matches
+pub fn expand_pathspec(x: (&str, bool)) -> Vec<String> {
+    // Ignored files are only included when adding is forced
+    let x: (&str, bool, String) = (x.0, x.1, normalize_path(x.0));
+
+    match is_glob(x.0) {
+        true => get_pathspec_files(("", x.1))
+            .into_iter()
+            .zip(std::iter::repeat(x.0))
+            .filter(|x| matches_pathspec((x.1, &x.0)))
+            .map(|x| x.0)
+            .collect(),
+        // Walking a directory starts inside it, so whether the directory itself (or one of the
+        // directories containing it) is ignored has to be checked first
+        false
+            if !x.2.is_empty()
+                && Path::new(&x.2).is_dir()
+                && !x.1
+                && ignore::is_ignored((&x.2, true)) =>
+        {
+            Vec::new()
+        }
+        false if x.2.is_empty() || Path::new(&x.2).is_dir() => get_pathspec_files((&x.2, x.1)),
+        false if Path::new(&x.2).is_file() => vec![x.2],
+        false => Vec::new(),
+    }
 }
 
+// The files below directory x.0 a pathspec can match, including ignored files if x.1
+fn get_pathspec_files(x: (&str, bool)) -> Vec<String> {
+    match x.1 {
+        true => get_all_working_tree_files(x.0),
+        false => get_working_tree_files(x.0),
+    }
+}
+
+// Expands a pathspec into the paths of tree x.1 it matches, whether or not they still exist in the
+// working tree
+pub fn expand_tracked_pathspec(x: (&str, &HashMap<String, String>)) -> BTreeSet<String> {
+    x.1.keys()
+        .zip(std::iter::repeat(x.0))
+        .filter(|x| matches_pathspec((x.1, x.0)))
+        .map(|x| x.0.clone())
+        .collect()
+}
+
+// Removes the parent directories of the path x that were left empty
+pub fn remove_empty_parents(x: &str) {
+    if let Some(x) = Path::new(x).parent().filter(|x| !x.as_os_str().is_empty()) {
+        if fs::remove_dir(x).is_ok() {
+            remove_empty_parents(&x.to_string_lossy());
+        }
+    }
+}
+
+// The names of the files directly in directory x that are named by (part of) a hex id
+fn list_hex_files(x: &str) -> Vec<String> {
+    match fs::read_dir(x) {
+        Ok(x) => x
+            .filter_map(|x| x.ok())
+            .filter(|x| x.path().is_file())
+            .map(|x| x.file_name().to_string_lossy().to_string())
+            .filter(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_hexdigit()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The ids of every object, both in .grit/objects and stored directly in .grit the way repositories
+// did before they were migrated
+pub fn get_object_ids() -> Vec<String> {
+    fs::read_dir(".grit/objects")
+        .map(|x| {
+            x.filter_map(|x| x.ok())
+                .map(|x| x.file_name().to_string_lossy().to_string())
+                .filter(|x| x.len() == 2 && x.bytes().all(|x| x.is_ascii_hexdigit()))
+                .flat_map(|x| {
+                    list_hex_files(&format!(".grit/objects/{x}"))
+                        .into_iter()
+                        .zip(std::iter::repeat(x))
+                        .map(|x| format!("{}{}", x.1, x.0))
+                })
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .chain(get_legacy_object_ids())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+// The ids of the objects that are still stored directly in .grit, where every file named by a hex
+// id is an object and anything else is repository metadata
+pub fn get_legacy_object_ids() -> Vec<String> {
+    list_hex_files(".grit")
+}
+
+// The message of commit (or tag) x, which is everything after the first empty line
 pub fn get_commit_message(x: &String) -> Option<String> {
-    let x = fs::read_to_string(format!(".grit/{x}"))
-        .expect(&format!("Could not get commit message for hash {x}"));
+    let x: Vec<u8> = read_object_of_kind((x, &["commit", "tag"]))
+        .unwrap_or_else(|| panic!("Could not get commit message for hash {x}"));
 
-    x.split("\n").last().map(|x| x.trim().to_string())
+    Some(parse_object(&String::from_utf8_lossy(&x)).1)
 }
 
-pub fn get_current_head() -> Option<String> {
-    match fs::read_to_string(".grit/HEAD") {
-        Ok(x) if !x.trim().is_empty() => Some(x),
-        _ => None,
+// The commit HEAD points to, following it through the current branch
+pub fn get_current_head() -> Option<ObjectId> {
+    read_ref("HEAD")
+}
+
+// The commit id stored in reference x (e.g. "HEAD" or "refs/heads/main"), following symbolic
+// references ("ref: <reference>") to the reference they name. None if it doesn't hold a valid id.
+pub fn read_ref(x: &str) -> Option<ObjectId> {
+    read_raw_ref(x)?.parse().ok()
+}
+
+// Like read_ref, but without checking that the reference holds a valid id. Only repositories that
+// still have to be migrated with `grit migrate-ids` have references to anything else.
+pub fn read_raw_ref(x: &str) -> Option<String> {
+    match fs::read_to_string(format!(".grit/{x}")).ok()?.trim() {
+        "" => None,
+        x if x.starts_with("ref: ") => read_raw_ref(x.trim_start_matches("ref: ")),
+        x => Some(x.to_string()),
+    }
+}
+
+// Points reference x.0 at commit x.1, creating it (and the directories it's in) if needed
+pub fn write_ref(x: (&str, &str)) {
+    let x: (String, &str) = (format!(".grit/{}", x.0), x.1);
+
+    if let Some(x) = Path::new(&x.0).parent() {
+        let _ = fs::create_dir_all(x);
+    }
+    let _ = fs::write(&x.0, format!("{}\n", x.1));
+}
+
+// The branch HEAD is on (e.g. "refs/heads/main"), None if HEAD is detached, i.e. contains a
+// commit id itself
+pub fn get_head_ref() -> Option<String> {
+    fs::read_to_string(".grit/HEAD")
+        .ok()?
+        .trim()
+        .strip_prefix("ref: ")
+        .map(String::from)
+}
+
+// Makes HEAD a symbolic reference to x (e.g. "refs/heads/main")
+pub fn set_head_ref(x: &str) {
+    let _ = fs::write(".grit/HEAD", format!("ref: {x}\n"));
+}
+
+// The identity and time recorded in logs. Moving refs doesn't need an identity the way committing
+// does, so it falls back to an unknown one.
+pub fn get_log_identity() -> String {
+    get_signature("COMMITTER").unwrap_or_else(|_| format!("unknown <> {}", get_current_date()))
+}
+
+// Appends an entry to the reflog of reference x.0, recording that it moved from commit x.1 (None
+// if it didn't exist) to commit x.2 because of x.3 (e.g. "commit: <subject>")
+pub fn append_reflog(x: (&str, Option<&str>, &str, &str)) {
+    let x: (String, String) = (
+        format!(".grit/logs/{}", x.0),
+        format!(
+            "{} {} {}\t{}\n",
+            x.1.unwrap_or(&"0".repeat(40)),
+            x.2,
+            get_log_identity(),
+            x.3.replace('\n', " ")
+        ),
+    );
+
+    if let Some(x) = Path::new(&x.0).parent() {
+        let _ = fs::create_dir_all(x);
+    }
+    if let Some(x) = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&x.0)
+        .ok()
+        .zip(Some(x.1))
+    {
+        let _ = (&x.0).write_all(x.1.as_bytes());
+    }
+}
+
+// Points reference x.0 at commit x.1 like write_ref, recording the move with the reason x.2 in its
+// reflog. Moving the branch HEAD is on moves HEAD as well, so it's recorded in HEAD's reflog too.
+pub fn update_ref(x: (&str, &str, &str)) {
+    let x: (&str, &str, &str, Option<String>) = (x.0, x.1, x.2, read_raw_ref(x.0));
+
+    write_ref((x.0, x.1));
+    append_reflog((x.0, x.3.as_deref(), x.1, x.2));
+    if get_head_ref().as_deref() == Some(x.0) {
+        append_reflog(("HEAD", x.3.as_deref(), x.1, x.2));
+    }
+}
+
+// Moves the current branch to commit x.0, or HEAD itself if it's detached, with the reason x.1
+// for the reflog
+pub fn update_head(x: (&str, &str)) {
+    update_ref((get_head_ref().as_deref().unwrap_or("HEAD"), x.0, x.1));
+}
+
+// Makes sure the repository has a refs/heads directory and a HEAD. Repositories created before
+// branches existed keep the commit their HEAD contained on a "main" branch.
+pub fn set_up_refs() {
+    if Path::new(".grit/refs/heads").is_dir() {
+        return;
+    }
+
+    let _ = fs::create_dir_all(".grit/refs/heads");
+    if let Some(x) = read_raw_ref("HEAD") {
+        write_ref(("refs/heads/main", &x));
+    }
+    set_head_ref("refs/heads/main");
+}
+
+// The names of every reference below the directory .grit/x, relative to it
+pub fn list_refs(x: &str) -> Vec<String> {
+    // The name of every entry of the directory and whether it's a directory itself
+    let x: (&str, Vec<(String, bool)>) = (
+        x,
+        fs::read_dir(format!(".grit/{x}"))
+            .map(|x| {
+                x.filter_map(|x| x.ok())
+                    .map(|x| {
+                        (
+                            x.file_name().to_string_lossy().to_string(),
+                            x.path().is_dir(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    );
+
+    x.1.iter()
+        .zip(std::iter::repeat(x.0))
+        .flat_map(|x| match x.0 .1 {
+            true => list_refs(&format!("{}/{}", x.1, x.0 .0))
+                .into_iter()
+                .zip(std::iter::repeat(&x.0 .0))
+                .map(|x| format!("{}/{}", x.1, x.0))
+                .collect(),
+            false => vec![x.0 .0.clone()],
+        })
+        .collect()
+}
+
+// The names of all branches, sorted
+pub fn get_branches() -> Vec<String> {
+    list_refs("refs/heads")
+        .into_iter()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+// The names of all tags, sorted
+pub fn get_tags() -> Vec<String> {
+    list_refs("refs/tags")
+        .into_iter()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+// The commit that annotated tag object x points at, or x itself if it isn't a tag object
+pub fn peel_tag(x: String) -> String {
+    match get_commit_header((Some(&x), "tag")).and(get_commit_header((Some(&x), "object"))) {
+        Some(x) => peel_tag(x),
+        None => x,
     }
 }
 
-// TODO: Combine the below two files into one that returns formatted "commit information"
+// Branch and tag names end up as paths below .grit/refs and are used in revisions, so they can't
+// contain anything that would be ambiguous in either place
+pub fn is_valid_ref_name(x: &str) -> bool {
+    !x.is_empty()
+        && x != "HEAD"
+        && x != "@"
+        && !x.starts_with('-')
+        && !x.ends_with(".lock")
+        && !x.contains("..")
+        && !x.contains("@{")
+        && !x
+            .chars()
+            .any(|x| x.is_whitespace() || x.is_control() || "~^:?*[\\".contains(x))
+        && x.split('/').all(|x| !x.is_empty() && !x.starts_with('.'))
+}
+
+// Splits the contents of a commit or tag object into its header lines (in the order they appear)
+// and its message. Only the lines before the first empty line are headers, everything after it is
+// the message. Commits from before messages were separated this way only have the message on the
+// last line.
+fn parse_object(x: &str) -> (Vec<(String, String)>, String) {
+    (
+        x.split("\n")
+            .take_while(|x| !x.is_empty())
+            .filter_map(|x| x.split_once(char::is_whitespace))
+            .map(|x| (x.0.to_string(), x.1.trim().to_string()))
+            .collect(),
+        x.split_once("\n\n")
+            .map(|x| x.1.trim_end().to_string())
+            .or_else(|| x.split("\n").last().map(|x| x.trim().to_string()))
+            .unwrap_or_default(),
+    )
+}
+
+// A commit object. It has one parent, or none for the first commit and several for a merge.
+#[derive(Clone, Default, Debug)]
+pub struct Commit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    // Every other header line (author, committer, ...) in the order they appear
+    pub headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+impl Commit {
+    // The value of the first header line named x
+    pub fn header(&self, x: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .zip(std::iter::repeat(x))
+            .find(|x| x.0 .0 == x.1)
+            .map(|x| &x.0 .1)
+    }
+}
+
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "tree\t{}", self.tree)?;
+        self.parents
+            .iter()
+            .try_for_each(|x| writeln!(f, "parent\t{x}"))?;
+        self.headers
+            .iter()
+            .try_for_each(|x| writeln!(f, "{}\t{}", x.0, x.1))?;
+        writeln!(f, "\n{}", self.message)
+    }
+}
+
+impl FromStr for Commit {
+    type Err = String;
+
+    fn from_str(x: &str) -> Result<Self, Self::Err> {
+        let x: (Vec<(String, String)>, String) = parse_object(x);
+
+        Ok(Commit {
+            tree: x
+                .0
+                .iter()
+                .find(|x| x.0 == "tree")
+                .map(|x| x.1.clone())
+                .ok_or("Commit has no tree")?,
+            parents: x
+                .0
+                .iter()
+                .filter(|x| x.0 == "parent")
+                .map(|x| x.1.clone())
+                .collect(),
+            headers: x
+                .0
+                .into_iter()
+                .filter(|x| x.0 != "tree" && x.0 != "parent")
+                .collect(),
+            message: x.1,
+        })
+    }
+}
+
+// The commit x, None if there's no such commit
+pub fn read_commit(x: Option<&String>) -> Option<Commit> {
+    String::from_utf8_lossy(&read_object_of_kind((x?, &["commit"]))?)
+        .parse::<Commit>()
+        .ok()
+}
+
+// The value of the header line x.1 (e.g. "object" or "tagger") of tag (or commit) x.0
+pub fn get_commit_header(x: (Option<&String>, &str)) -> Option<String> {
+    let x: (Vec<(String, String)>, &str) = (
+        parse_object(&String::from_utf8_lossy(&read_object_of_kind((
+            x.0?,
+            &["commit", "tag"],
+        ))?))
+        .0,
+        x.1,
+    );
+
+    x.0.into_iter()
+        .zip(std::iter::repeat(x.1))
+        .find(|x| x.0 .0 == x.1)
+        .map(|x| x.0 .1)
+}
+
+// The first parent of commit x
 pub fn get_parent_of_commit(x: Option<&String>) -> Option<String> {
-    // Highly maintainable piece of code
+    read_commit(x)?.parents.into_iter().next()
+}
+
+// Every parent of commit x, in order
+pub fn get_parents_of_commit(x: Option<&String>) -> Vec<String> {
+    read_commit(x).map(|x| x.parents).unwrap_or_default()
+}
+
+pub fn get_tree_of_commit(x: Option<&String>) -> Option<String> {
+    read_commit(x).map(|x| x.tree)
+}
+
+// The value of field x.1 ("NAME", "EMAIL" or "DATE") of identity x.0 ("AUTHOR" or "COMMITTER").
+// The committer's name and email fall back to the author's environment variables, and both fall
+// back to the "user.name" and "user.email" config keys. The date is never taken from the author,
+// so that a commit made with an explicit author date is still committed at the current time.
+fn get_identity_field(x: (&str, &str)) -> Option<String> {
+    env::var(format!("GRIT_{}_{}", x.0, x.1))
+        .ok()
+        .filter(|x| !x.trim().is_empty())
+        .or_else(|| {
+            env::var(format!("GRIT_AUTHOR_{}", x.1))
+                .ok()
+                .filter(|_| x.1 != "DATE")
+                .filter(|x| !x.trim().is_empty())
+        })
+        .or_else(|| config::get(&format!("user.{}", x.1.to_lowercase())))
+        .map(|x| x.trim().to_string())
+}
+
+// The offset of the local time zone from UTC at unix timestamp x, like "+0200"
+fn get_local_offset(x: i64) -> String {
+    Local
+        .timestamp_opt(x, 0)
+        .earliest()
+        .map_or(String::from("+0000"), |x| x.format("%z").to_string())
+}
+
+// The current time in the format "timestamp offset", in the local time zone
+pub fn get_current_date() -> String {
+    Local::now().format("%s %z").to_string()
+}
+
+// Parses a date in the format "<unix timestamp> [<+hhmm|-hhmm>]" (the timestamp may be prefixed
+// with `@`). Dates without an offset are in the local time zone.
+fn parse_date(x: &str) -> Option<String> {
+    let x: Vec<&str> = x.split_whitespace().collect();
+    let x: (i64, Option<&&str>, usize) = (
+        x.first()?.trim_start_matches('@').parse::<i64>().ok()?,
+        x.get(1),
+        x.len(),
+    );
+    let x: (i64, Option<String>, usize) = (
+        x.0,
+        x.1.map_or(Some(get_local_offset(x.0)), |x| {
+            Some(x.to_string()).filter(|x| {
+                x.len() == 5
+                    && (x.starts_with('+') || x.starts_with('-'))
+                    && x[1..].bytes().all(|x| x.is_ascii_digit())
+            })
+        }),
+        x.2,
+    );
+
+    // Anything after the offset makes the date invalid
+    match x.2 {
+        1 | 2 => Some(format!("{} {}", x.0, x.1?)),
+        _ => None,
+    }
+}
+
+// The identity lines of the author and the committer of a new commit, in the format (author,
+// committer)
+pub type Signatures = (String, String);
+
+// The identity line of x ("AUTHOR" or "COMMITTER") in the format "Name <email> timestamp offset",
+// using the current time unless a date is set
+pub fn get_signature(x: &str) -> Result<String, String> {
+    let x: (&str, Option<String>, Option<String>, Option<String>) = (
+        x,
+        get_identity_field((x, "NAME")),
+        get_identity_field((x, "EMAIL")),
+        get_identity_field((x, "DATE")),
+    );
+
+    Ok(format!(
+        "{} <{}> {}",
+        x.1.ok_or(format!(
+            "Could not determine the {} name, set GRIT_{}_NAME or user.name",
+            x.0.to_lowercase(),
+            x.0
+        ))?
+        .replace(['<', '>', '\n'], ""),
+        x.2.ok_or(format!(
+            "Could not determine the {} email, set GRIT_{}_EMAIL or user.email",
+            x.0.to_lowercase(),
+            x.0
+        ))?
+        .replace(['<', '>', '\n'], ""),
+        match x.3 {
+            Some(x) => parse_date(&x).ok_or(format!("Invalid date '{x}'"))?,
+            None => get_current_date(),
+        }
+    ))
+}
+
+// The reference a reflog revision ("<ref>@{n}") reads the log of. A bare "@{n}" is the current
+// branch, or HEAD when it's detached.
+pub fn get_reflog_ref(x: &str) -> Option<String> {
     match x {
-        Some(ref x) => match fs::read_to_string(format!(".grit/{x}")) {
-            Ok(x) => x
-                .split("\n")
-                .nth(1)
-                .map(|x| x.split_whitespace().nth(1))
-                .flatten()
-                .map(|x| x.to_string()),
-            _ => None,
-        },
+        "" => get_head_ref().or(Some(String::from("HEAD"))),
+        "HEAD" | "@" => Some(String::from("HEAD")),
+        x if is_valid_ref_name(x) && Path::new(&format!(".grit/refs/heads/{x}")).is_file() => {
+            Some(format!("refs/heads/{x}"))
+        }
+        x if is_valid_ref_name(x) && Path::new(&format!(".grit/refs/tags/{x}")).is_file() => {
+            Some(format!("refs/tags/{x}"))
+        }
+        "stash" if Path::new(".grit/refs/stash").is_file() => Some(String::from("refs/stash")),
         _ => None,
     }
 }
 
-pub fn get_tree_of_commit(x: Option<&String>) -> Option<String> {
+// An entry of a reflog in the format (old id, new id, identity, reason)
+pub type ReflogEntry = (String, String, String, String);
+
+// The entries of the reflog of reference x, oldest first. Every line of .grit/logs/<ref> is
+// "<old> <new> <identity>\t<reason>".
+pub fn get_reflog(x: &str) -> Vec<ReflogEntry> {
+    fs::read_to_string(format!(".grit/logs/{x}"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| {
+            let x: (&str, &str) = x.split_once('\t').unwrap_or((x, ""));
+            let x: (Vec<&str>, &str) = (x.0.splitn(3, ' ').collect(), x.1);
+
+            Some((
+                x.0.first()?.to_string(),
+                x.0.get(1)?.to_string(),
+                x.0.get(2).unwrap_or(&"").to_string(),
+                x.1.to_string(),
+            ))
+        })
+        .collect()
+}
+
+// Resolves the base of a revision, which is everything before its `~` and `^` suffixes: HEAD (or
+// @), a branch or tag name, "stash", a full object id, an unambiguous prefix of at least 4
+// characters of one, or "<ref>@{n}" for where a reference was n movements ago
+fn resolve_base_revision(x: &str) -> Result<ObjectId, String> {
+    if let Some(x) = x
+        .strip_suffix('}')
+        .and_then(|x| x.rsplit_once("@{"))
+        .filter(|x| !x.1.is_empty() && x.1.bytes().all(|x| x.is_ascii_digit()))
+    {
+        let x: (String, usize) = (
+            get_reflog_ref(x.0).ok_or(format!("Unknown revision '{}'", x.0))?,
+            x.1.parse::<usize>()
+                .map_err(|_| format!("Invalid reflog entry '{}'", x.1))?,
+        );
+        let x: (String, usize, Vec<ReflogEntry>) = (x.0.clone(), x.1, get_reflog(&x.0));
+
+        return x
+            .2
+            .iter()
+            .rev()
+            .nth(x.1)
+            .ok_or(match x.2.len() {
+                0 => format!("No reflog for '{}'", x.0),
+                _ => format!("Reflog for '{}' only has {} entries", x.0, x.2.len()),
+            })?
+            .1
+            .parse();
+    }
+
     match x {
-        Some(ref x) => match fs::read_to_string(format!(".grit/{x}")) {
-            Ok(x) => x
-                .split("\n")
-                .next()
-                .map(|x| x.split_whitespace().nth(1))
-                .flatten()
-                .map(|x| x.to_string()),
-            _ => None,
+        "HEAD" | "@" => get_current_head().ok_or(format!("Unknown revision '{x}'")),
+        x if is_valid_ref_name(x) && Path::new(&format!(".grit/refs/heads/{x}")).is_file() => {
+            read_ref(&format!("refs/heads/{x}")).ok_or(format!("Unknown revision '{x}'"))
+        }
+        x if is_valid_ref_name(x) && Path::new(&format!(".grit/refs/tags/{x}")).is_file() => {
+            read_ref(&format!("refs/tags/{x}"))
+                .ok_or(format!("Unknown revision '{x}'"))
+                .and_then(|x| peel_tag(x.to_string()).parse())
+        }
+        // The latest stash entry
+        "stash" if Path::new(".grit/refs/stash").is_file() => {
+            read_ref("refs/stash").ok_or(format!("Unknown revision '{x}'"))
+        }
+        // A full id names a single object, which only has to exist
+        x if x.len() == 40 && x.bytes().all(|x| x.is_ascii_hexdigit()) => {
+            Some(x.to_ascii_lowercase())
+                .filter(|x| find_object_file(x).is_some())
+                .ok_or(format!("Unknown revision '{x}'"))?
+                .parse()
+        }
+        x if x.len() >= 4 && x.bytes().all(|x| x.is_ascii_hexdigit()) => {
+            let x: (&str, Vec<String>) = (
+                x,
+                get_object_ids()
+                    .into_iter()
+                    .zip(std::iter::repeat(x.to_ascii_lowercase()))
+                    .filter(|x| x.0.starts_with(&x.1))
+                    .map(|x| x.0)
+                    .collect::<BTreeSet<String>>()
+                    .into_iter()
+                    .collect(),
+            );
+
+            match x.1.as_slice() {
+                [] => Err(format!("Unknown revision '{}'", x.0)),
+                [x] => x.parse(),
+                _ => Err(format!(
+                    "Short object id '{}' is ambiguous, it could be any of:\n{}",
+                    x.0,
+                    x.1.iter()
+                        .map(|x| format!("  {x}"))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )),
+            }
+        }
+        x => Err(format!("Unknown revision '{x}'")),
+    }
+}
+
+// Follows the `~<n>` (n-th generation ancestor, following first parents) and `^<n>` (n-th parent)
+// suffixes x.1 of revision x.2, starting at commit x.0. A suffix without a number means 1.
+fn follow_suffixes(x: (String, &str, &str)) -> Result<String, String> {
+    if x.1.is_empty() {
+        return Ok(x.0);
+    }
+
+    // The operator, its number and the suffixes after it
+    let x: (String, char, &str, &str) =
+        (x.0, x.1.chars().next().unwrap_or_default(), &x.1[1..], x.2);
+    let x: (String, char, &str, &str, &str) = (
+        x.0,
+        x.1,
+        &x.2[..x.2.bytes().take_while(|x| x.is_ascii_digit()).count()],
+        &x.2[x.2.bytes().take_while(|x| x.is_ascii_digit()).count()..],
+        x.3,
+    );
+    let x: (String, char, usize, &str, &str) = (
+        x.0,
+        x.1,
+        match x.2 {
+            "" => 1,
+            x => x
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid number '{x}'"))?,
         },
-        _ => None,
+        x.3,
+        x.4,
+    );
+
+    let x: (Option<String>, &str, &str) = (
+        match x.1 {
+            '~' => (0..x.2).try_fold(x.0, |x, _| get_parent_of_commit(Some(&x))),
+            '^' if x.2 == 0 => Some(x.0),
+            '^' => get_parents_of_commit(Some(&x.0)).into_iter().nth(x.2 - 1),
+            _ => return Err(format!("Invalid revision '{}'", x.4)),
+        },
+        x.3,
+        x.4,
+    );
+
+    follow_suffixes((
+        x.0.ok_or(format!(
+            "Revision '{}' goes past the history it refers to",
+            x.2
+        ))?,
+        x.1,
+        x.2,
+    ))
+}
+
+// Resolves revision x to the id of the commit it names: a base revision (see
+// resolve_base_revision) followed by any number of `~<n>` and `^<n>` suffixes, e.g. "HEAD~3",
+// "main^2" or "v1.0~2^". Annotated tags resolve to the commit they point at.
+pub fn resolve_revision(x: &str) -> Result<ObjectId, String> {
+    let x: (&str, usize) = (x, x.find(['~', '^']).unwrap_or(x.len()));
+    let x: (&str, &str, &str) = (&x.0[..x.1], &x.0[x.1..], x.0);
+
+    follow_suffixes((resolve_base_revision(x.0)?.to_string(), x.1, x.2))?.parse()
+}
+
+// Splits "<revision>:<path>" into its revision and path, where an empty revision means HEAD. None
+// if x doesn't name a path.
+pub fn split_revision_path(x: &str) -> Option<(&str, &str)> {
+    match x.split_once(':')? {
+        ("", x) => Some(("HEAD", x)),
+        x => Some(x),
     }
 }
 
-// The tree is represented by a HashMap of format (filepath, hash).
-// As such, only one hash can be active for any single filepath.
-pub fn get_tree(x: Option<&String>) -> HashMap<String, String> {
+// Resolves x to an object id. Besides every form of revision, "<revision>:<path>" names the file at
+// path in that revision, or its root tree if the path is empty.
+pub fn resolve_object(x: &str) -> Result<ObjectId, String> {
+    match split_revision_path(x) {
+        Some(x) => {
+            let x: (&str, &str, Option<String>, String) = (
+                x.0,
+                x.1,
+                get_tree_of_commit(Some(&resolve_revision(x.0)?.to_string())),
+                normalize_path(x.1),
+            );
+
+            match x.3.is_empty() {
+                true => x.2,
+                false => get_tree_path((x.2.as_ref(), &x.3)),
+            }
+            .ok_or(format!("Path '{}' does not exist in '{}'", x.1, x.0))?
+            .parse()
+        }
+        None => resolve_revision(x),
+    }
+}
+
+// The abbreviated form of object id x that's shown to the user
+pub fn abbreviate(x: &str) -> String {
+    x.chars().take(7).collect()
+}
+
+// Commit x and every commit it descends from through any of its parents, nearest first
+pub fn get_ancestors(x: &str) -> Vec<String> {
+    let x: (String, RefCell<BTreeSet<String>>) =
+        (x.to_string(), RefCell::new(BTreeSet::from([x.to_string()])));
+
+    // Every step goes one generation further back, skipping commits that were already reached
+    std::iter::successors(Some((vec![x.0.clone()], &x.1)), |x| {
+        Some((
+            x.0.iter()
+                .flat_map(|x| get_parents_of_commit(Some(x)))
+                .zip(std::iter::repeat(x.1))
+                .filter(|x| x.1.borrow_mut().insert(x.0.clone()))
+                .map(|x| x.0)
+                .collect::<Vec<String>>(),
+            x.1,
+        ))
+        .filter(|x| !x.0.is_empty())
+    })
+    .flat_map(|x| x.0)
+    .collect()
+}
+
+// The nearest common ancestor of commits x.0 and x.1, which a merge of them starts from. A
+// common ancestor that is itself an ancestor of another common ancestor is never the nearest one,
+// and since every commit between the two is a common ancestor too, it's enough to skip the
+// parents of common ancestors
+pub fn get_merge_base(x: (&String, &String)) -> Option<String> {
+    let x: (Vec<String>, BTreeSet<String>) =
+        (get_ancestors(x.0), get_ancestors(x.1).into_iter().collect());
+    let x: Vec<String> =
+        x.0.into_iter()
+            .zip(std::iter::repeat(&x.1))
+            .filter(|x| x.1.contains(&x.0))
+            .map(|x| x.0)
+            .collect();
+    let x: (Vec<String>, BTreeSet<String>) = (
+        x.clone(),
+        x.iter()
+            .flat_map(|x| get_parents_of_commit(Some(x)))
+            .collect(),
+    );
+
+    x.0.into_iter()
+        .zip(std::iter::repeat(&x.1))
+        .find(|x| !x.1.contains(&x.0))
+        .map(|x| x.0)
+}
+
+// Commits mapped to the number of their children that are still waiting to be listed
+type ChildCounts = RefCell<HashMap<String, usize>>;
+
+// Commit x and all of its ancestors, with every commit coming before its parents. The first
+// parent's side of a merge is followed before the other ones.
+pub fn get_history(x: Option<String>) -> Vec<String> {
+    // How many children every commit has that haven't been listed yet, and the commits whose
+    // children all have been
+    let x: (Vec<String>, ChildCounts, RefCell<Vec<String>>) = (
+        x.as_deref().map(get_ancestors).unwrap_or_default(),
+        RefCell::new(HashMap::new()),
+        RefCell::new(x.into_iter().collect()),
+    );
+    x.0.iter()
+        .flat_map(|x| get_parents_of_commit(Some(x)))
+        .zip(std::iter::repeat(&x.1))
+        .for_each(|x| *x.1.borrow_mut().entry(x.0).or_insert(0) += 1);
+
+    std::iter::from_fn(|| {
+        let x: (String, &ChildCounts, &RefCell<Vec<String>>) =
+            (x.2.borrow_mut().pop()?, &x.1, &x.2);
+
+        // The parents are added in reverse, so that the first one is listed next
+        get_parents_of_commit(Some(&x.0))
+            .into_iter()
+            .rev()
+            .zip(std::iter::repeat((x.1, x.2)))
+            .for_each(|x| {
+                let x: (String, &RefCell<Vec<String>>, usize) = (
+                    x.0.clone(),
+                    x.1 .1,
+                    x.1 .0.borrow_mut().get_mut(&x.0).map_or(0, |x| {
+                        *x -= 1;
+                        *x
+                    }),
+                );
+
+                if x.2 == 0 {
+                    x.1.borrow_mut().push(x.0);
+                }
+            });
+
+        Some(x.0)
+    })
+    .collect()
+}
+
+// Every path whose contents differ between the trees x.0 and x.1
+pub fn get_changed_paths(x: (Option<&String>, Option<&String>)) -> BTreeSet<String> {
+    get_differing_paths((&get_tree(x.0), &get_tree(x.1)))
+}
+
+// Every path whose contents differ between the flattened trees x.0 and x.1
+pub fn get_differing_paths(x: (&Tree, &Tree)) -> BTreeSet<String> {
+    x.0.keys()
+        .chain(x.1.keys())
+        .zip(std::iter::repeat(x))
+        .filter(|x| x.1 .0.get(x.0) != x.1 .1.get(x.0))
+        .map(|x| x.0.clone())
+        .collect()
+}
+
+// An author or committer line split into its name, email and date ("timestamp offset")
+pub type ParsedSignature = (String, String, String);
+
+pub fn parse_signature(x: &str) -> Option<ParsedSignature> {
+    let x: (&str, &str) = x.split_once(" <")?;
+    let x: (&str, (&str, &str)) = (x.0, x.1.split_once("> ")?);
+
+    Some((x.0.to_string(), x.1 .0.to_string(), x.1 .1.to_string()))
+}
+
+// Formats a date in the format "timestamp offset" like "Tue Nov 14 22:13:20 2023 +0200", in the
+// timezone of the offset
+pub fn format_date(x: &str) -> Option<String> {
+    let x: (i64, &str) = x
+        .split_once(' ')
+        .and_then(|x| Some((x.0.parse::<i64>().ok()?, x.1)))?;
+
+    // The offset is printed as it was written rather than as chrono would format it
+    let x: (DateTime<FixedOffset>, &str) = (
+        DateTime::from_timestamp(x.0, 0)?.with_timezone(&FixedOffset::east_opt(
+            match x.1.starts_with('-') {
+                true => -1,
+                false => 1,
+            } * (x.1.get(1..3)?.parse::<i32>().ok()? * 3600
+                + x.1.get(3..5)?.parse::<i32>().ok()? * 60),
+        )?),
+        x.1,
+    );
+
+    Some(format!("{} {}", x.0.format("%a %b %-d %H:%M:%S %Y"), x.1))
+}
+
+// A flattened tree in the format (filepath -> blob hash)
+pub type Tree = HashMap<String, String>;
+
+// An entry of a tree object in the format (kind, hash, name)
+pub type TreeEntry = (String, String, String);
+
+// A change to be applied to a tree in the format (filepath, blob hash), where a missing hash
+// removes the file from the tree
+pub type TreeChange = (String, Option<String>);
+
+// The entries of one level of a tree in the format (name -> (kind, hash))
+type TreeLevel = BTreeMap<String, (String, String)>;
+
+// A single level of a tree object as (kind, hash, name) entries, where kind is either "blob" or
+// "tree". Trees written before directories got their own tree objects list files by their full
+// path instead of just their name.
+pub fn get_tree_entries(x: Option<&String>) -> Vec<TreeEntry> {
     match x {
         Some(x) if !x.is_empty() => {
-            let x: String = fs::read_to_string(format!(".grit/{x}"))
-                .expect("Could not open previous parent file");
+            let x: String = String::from_utf8_lossy(
+                &read_object_of_kind((x, &["tree"]))
+                    .unwrap_or_else(|| panic!("Could not open tree object {x}")),
+            )
+            .to_string();
 
             x.lines()
                 .filter_map(|x| {
+                    let x: Vec<&str> = x.splitn(3, '\t').collect();
                     Some((
-                        x.split_whitespace().nth(2)?.to_string(),
-                        x.split_whitespace().nth(1)?.to_string(),
+                        x.first()?.to_string(),
+                        x.get(1)?.to_string(),
+                        x.get(2)?.to_string(),
                     ))
                 })
                 .collect()
         }
-        _ => HashMap::new(),
+        _ => Vec::new(),
+    }
+}
+
+// The tree is represented by a HashMap of format (filepath, hash), with the contents of every
+// subtree flattened into paths relative to the root of the tree.
+// As such, only one hash can be active for any single filepath.
+pub fn get_tree(x: Option<&String>) -> HashMap<String, String> {
+    get_tree_entries(x)
+        .into_iter()
+        .flat_map(|x| match x.0.as_str() {
+            "tree" => get_tree(Some(&x.1))
+                .into_iter()
+                .zip(std::iter::repeat(x.2))
+                .map(|x| (format!("{}/{}", x.1, x.0 .0), x.0 .1))
+                .collect::<Vec<(String, String)>>(),
+            _ => vec![(x.2, x.1)],
+        })
+        .collect()
+}
+
+// The files of tree x.0 that are in directory x.1 (or are the file x.1 itself), as a HashMap in the
+// same format as get_tree. Only the subtrees along the path are read, so looking up a single
+// directory doesn't cost as much as reading the whole tree.
+pub fn get_tree_under(x: (Option<&String>, &str)) -> HashMap<String, String> {
+    if x.1.is_empty() {
+        return get_tree(x.0);
+    }
+
+    get_tree_entries(x.0)
+        .into_iter()
+        .zip(std::iter::repeat(x.1))
+        .flat_map(|x| match x.0 .0.as_str() {
+            "tree" => Some(&x.0 .1)
+                .zip(
+                    x.1.strip_prefix(x.0 .2.as_str())
+                        .filter(|x| x.is_empty() || x.starts_with('/')),
+                )
+                .map(|x| get_tree_under((Some(x.0), x.1.trim_start_matches('/'))))
+                .unwrap_or_default()
+                .into_iter()
+                .zip(std::iter::repeat(&x.0 .2))
+                .map(|x| (format!("{}/{}", x.1, x.0 .0), x.0 .1))
+                .collect::<Vec<(String, String)>>(),
+            // Legacy flat trees list nested files by their full path
+            _ if matches_pathspec((x.1, &x.0 .2)) => vec![(x.0 .2, x.0 .1)],
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+// Looks up the hash of a single file in a tree, only descending into the subtrees along its path
+pub fn get_tree_path(x: (Option<&String>, &str)) -> Option<String> {
+    let x: (Vec<TreeEntry>, &str) = (get_tree_entries(x.0), x.1);
+
+    // Either the whole path is a file at this level (which is also how legacy flat trees store
+    // nested files), or the first component of the path is a subtree to descend into
+    x.0.iter()
+        .zip(std::iter::repeat(x.1))
+        .find_map(|x| match x.0 .0.as_str() {
+            "blob" if x.0 .2 == x.1 => Some(x.0 .1.clone()),
+            "tree" => get_tree_path((
+                Some(&x.0 .1),
+                x.1.strip_prefix(x.0 .2.as_str())?.strip_prefix('/')?,
+            )),
+            _ => None,
+        })
+}
+
+// The kinds of objects there are. Every object starts with a "<kind> <size>\0" header, which
+// is part of what its id is the hash of.
+const OBJECT_KINDS: [&str; 4] = ["blob", "tree", "commit", "tag"];
+
+// The id an object of kind x.0 with contents x.1 is stored under, the same as git would give it
+pub fn hash_object(x: (&str, &[u8])) -> ObjectId {
+    hash_bytes(&[format!("{} {}\0", x.0, x.1.len()).as_bytes(), x.1].concat())
+}
+
+// The id a file with contents x has once it's stored as a blob
+pub fn hash_blob(x: &[u8]) -> String {
+    hash_object(("blob", x)).to_string()
+}
+
+// The kind and contents of an object that has a header
+type ParsedObject = (String, Vec<u8>);
+
+// Splits the decompressed object x (with id x.1) into its kind and contents, making sure the
+// header is valid and the size in it matches
+fn parse_object_header(x: (Vec<u8>, &str)) -> Result<ParsedObject, String> {
+    let x: (Vec<u8>, &str, Option<usize>) = (x.0.clone(), x.1, x.0.iter().position(|x| *x == 0));
+    let x: (String, Vec<u8>, &str) = (
+        String::from_utf8_lossy(&x.0[..x.2.ok_or(format!("Object {} has no header", x.1))?])
+            .to_string(),
+        x.0[x.2.unwrap_or_default() + 1..].to_vec(),
+        x.1,
+    );
+    let x: (Option<(&str, &str)>, Vec<u8>, &str) = (x.0.split_once(' '), x.1, x.2);
+
+    match x.0 {
+        Some(x) if !OBJECT_KINDS.contains(&x.0) => Err(format!("Unknown object kind '{}'", x.0)),
+        Some(_) if x.0.and_then(|x| x.1.parse::<usize>().ok()) != Some(x.1.len()) => Err(format!(
+            "Object {} does not have the size its header says",
+            x.2
+        )),
+        Some(_) => Ok((x.0.unwrap_or_default().0.to_string(), x.1)),
+        None => Err(format!("Object {} has an invalid header", x.2)),
+    }
+}
+
+// Where object x is stored: in a directory below .grit/objects named after the first two
+// characters of its id, so that no single directory ends up with every object in it
+pub fn get_object_path(x: &str) -> String {
+    format!(
+        ".grit/objects/{}/{}",
+        x.get(..2).unwrap_or_default(),
+        x.get(2..).unwrap_or_default()
+    )
+}
+
+// The file object x is in, which is directly in .grit for repositories that weren't migrated to
+// .grit/objects yet. Only hex ids are looked up there, so that no metadata is taken for an object.
+fn find_object_file(x: &str) -> Option<String> {
+    Some(x)
+        .filter(|x| x.len() > 2 && x.bytes().all(|x| x.is_ascii_hexdigit()))
+        .and_then(|x| {
+            [get_object_path(x), format!(".grit/{x}")]
+                .into_iter()
+                .find(|x| Path::new(x).is_file())
+        })
+}
+
+// Removes object x from wherever it's stored
+pub fn remove_object(x: &str) {
+    if let Some(x) = find_object_file(x) {
+        let _ = fs::remove_file(&x);
+        remove_empty_parents(&x);
+    }
+}
+
+// Moves every object stored directly in .grit to its place in .grit/objects, returning how many
+// there were. Objects from before objects had a header are left where they are, since only
+// `grit migrate-ids` can tell what kind of object they are.
+pub fn move_legacy_objects() -> usize {
+    get_legacy_object_ids()
+        .iter()
+        .filter(|x| read_object_with_kind(x).is_ok_and(|x| x.is_some_and(|x| x.0.is_some())))
+        .map(|x| {
+            let x: (String, String) = (format!(".grit/{x}"), get_object_path(x));
+            if let Some(x) = Path::new(&x.1).parent() {
+                let _ = fs::create_dir_all(x);
+            }
+
+            // An identical object might have been written to .grit/objects already
+            match Path::new(&x.1).exists() {
+                true => fs::remove_file(&x.0),
+                false => fs::rename(&x.0, &x.1),
+            }
+        })
+        .filter(|x| x.is_ok())
+        .count()
+}
+
+// The kind and contents of an object. Legacy objects have no kind.
+pub type TypedObject = (Option<String>, Vec<u8>);
+
+// The kind and contents of object x, None if there's no such object and an error if it's broken
+pub fn read_object_with_kind(x: &str) -> Result<Option<TypedObject>, String> {
+    let x: (&str, String) = match find_object_file(x).zip(Some(x)) {
+        Some(x) => (x.1, x.0),
+        None => return Ok(None),
+    };
+
+    match fs::read(&x.1).ok().zip(Some(x)) {
+        Some(x) => decode_object((x.0, x.1 .0, x.1 .1.starts_with(".grit/objects/"))).map(Some),
+        None => Ok(None),
+    }
+}
+
+// The kind and contents of the stored object x.0 with id x.1, where x.2 is whether it's stored in
+// .grit/objects. Objects are stored compressed with zlib. Objects written before they had a header
+// weren't compressed, those are read as they are and have no kind. They can only be directly in
+// .grit, since moving objects into .grit/objects needs their kind, so whether an object is a
+// legacy one is decided by where it's stored rather than by whether it happens to decompress.
+fn decode_object(x: (Vec<u8>, &str, bool)) -> Result<TypedObject, String> {
+    let x: (Vec<u8>, &str, bool, Option<Vec<u8>>) = (
+        x.0.clone(),
+        x.1,
+        x.2,
+        BufReader::new(ZlibDecoder::new(x.0.as_slice()))
+            .bytes()
+            .collect::<Result<Vec<u8>, std::io::Error>>()
+            .ok(),
+    );
+    let x: (Vec<u8>, bool, Result<ParsedObject, String>) = (
+        x.0,
+        x.2,
+        x.3.zip(Some(x.1)).map_or_else(
+            || Err(format!("Object {} is not compressed", x.1)),
+            parse_object_header,
+        ),
+    );
+
+    match x {
+        (_, _, Ok(x)) => Ok((Some(x.0), x.1)),
+        (x, false, Err(_)) => Ok((None, x)),
+        (_, true, Err(x)) => Err(x),
+    }
+}
+
+// The contents of object x, None if there's no such object or it can't be read
+pub fn read_object(x: &str) -> Option<Vec<u8>> {
+    read_object_with_kind(x).ok().flatten().map(|x| x.1)
+}
+
+// The contents of object x.0 if it's one of the kinds x.1. Legacy objects can be of any kind.
+pub fn read_object_of_kind(x: (&str, &[&str])) -> Option<Vec<u8>> {
+    let x: (Option<TypedObject>, &[&str]) = (read_object_with_kind(x.0).ok().flatten(), x.1);
+    let x: (Option<Vec<u8>>, bool) = (
+        x.0.as_ref().map(|x| x.1.clone()),
+        x.0.and_then(|x| x.0)
+            .zip(Some(x.1))
+            .is_none_or(|x| x.1.contains(&x.0.as_str())),
+    );
+
+    x.0.filter(|_| x.1)
+}
+
+// The object x (header included) the way it's stored, compressed with zlib
+fn compress_object(x: &[u8]) -> Vec<u8> {
+    BufReader::new(ZlibEncoder::new(x, Compression::default()))
+        .bytes()
+        .collect::<Result<Vec<u8>, std::io::Error>>()
+        .expect("Could not compress object")
+}
+
+// Writes an object of kind x.0 with the contents x.1 if an identical one isn't already stored,
+// returning its id
+pub fn write_object(x: (&str, &[u8])) -> ObjectId {
+    let x: (ObjectId, Vec<u8>) = (
+        hash_object(x),
+        [format!("{} {}\0", x.0, x.1.len()).as_bytes(), x.1].concat(),
+    );
+    let x: (ObjectId, Vec<u8>, String) = (x.0, x.1, x.0.to_string());
+
+    if find_object_file(&x.2).is_none() {
+        if let Some(x) = Path::new(&get_object_path(&x.2)).parent() {
+            let _ = fs::create_dir_all(x);
+        }
+        let _ = File::create(get_object_path(&x.2))
+            .expect("Could not create object")
+            .write_all(&compress_object(&x.1));
+    }
+
+    x.0
+}
+// Directories (first path components) that have at least one change inside them
+fn get_changed_directories(x: &[TreeChange]) -> BTreeSet<String> {
+    x.iter()
+        .filter_map(|x| x.0.split_once('/'))
+        .map(|x| x.0.to_string())
+        .collect()
+}
+
+// The changes inside directory x.0, with paths relative to that directory
+fn get_changes_under(x: (&String, &[TreeChange])) -> Vec<TreeChange> {
+    x.1.iter()
+        .zip(std::iter::repeat(x.0))
+        .filter_map(|x| {
+            Some((
+                x.0 .0
+                    .strip_prefix(x.1.as_str())?
+                    .strip_prefix('/')?
+                    .to_string(),
+                x.0 .1.clone(),
+            ))
+        })
+        .collect()
+}
+
+// Writes a new version of tree x.0 with the changes x.1 applied, with filepaths relative to this
+// tree. Subtrees without changes are reused as-is, so only the directories along the changed
+// paths get rewritten. Returns None if the tree would be empty, so that directories whose files
+// were all removed disappear from their parent tree.
+fn write_tree(x: (Option<&String>, Vec<TreeChange>)) -> Option<String> {
+    let x: (Vec<TreeEntry>, Vec<TreeChange>) = (get_tree_entries(x.0), x.1);
+
+    // Entries of legacy flat trees that are in a subdirectory are moved into their own subtree
+    // just like any other change would be
+    let x: (TreeLevel, Vec<TreeChange>) = (
+        x.0.iter()
+            .filter(|x| !x.2.contains('/'))
+            .map(|x| (x.2.clone(), (x.0.clone(), x.1.clone())))
+            .collect(),
+        x.0.iter()
+            .filter(|x| x.2.contains('/'))
+            .map(|x| (x.2.clone(), Some(x.1.clone())))
+            .chain(x.1)
+            .collect(),
+    );
+
+    // Changes to files directly in this tree simply replace (or remove) the previous entry
+    let x: (TreeLevel, Vec<TreeChange>) = (
+        x.0.into_iter()
+            .zip(std::iter::repeat(&x.1))
+            .filter(|x| {
+                !x.1.iter()
+                    .zip(std::iter::repeat(&x.0 .0))
+                    .any(|x| x.0 .0 == *x.1)
+            })
+            .map(|x| x.0)
+            .chain(
+                x.1.iter()
+                    .filter(|x| !x.0.contains('/'))
+                    .filter_map(|x| Some((x.0.clone(), ("blob".to_string(), x.1.clone()?)))),
+            )
+            .collect(),
+        x.1.into_iter().filter(|x| x.0.contains('/')).collect(),
+    );
+
+    // Every directory with changes in it gets its subtree rewritten
+    let x: (TreeLevel, BTreeMap<String, Option<String>>) = (
+        x.0.clone(),
+        get_changed_directories(&x.1)
+            .into_iter()
+            .zip(std::iter::repeat((&x.0, &x.1)))
+            .map(|x| {
+                (
+                    x.0.clone(),
+                    write_tree((
+                        x.1 .0.get(&x.0).filter(|x| x.0 == "tree").map(|x| &x.1),
+                        get_changes_under((&x.0, x.1 .1)),
+                    )),
+                )
+            })
+            .collect(),
+    );
+
+    let x: String =
+        x.0.iter()
+            .zip(std::iter::repeat(&x.1))
+            .filter(|x| !x.1.contains_key(x.0 .0))
+            .map(|x| (x.0 .0.clone(), x.0 .1.clone()))
+            .chain(
+                x.1.iter()
+                    .filter_map(|x| Some((x.0.clone(), ("tree".to_string(), x.1.clone()?)))),
+            )
+            .collect::<TreeLevel>()
+            .iter()
+            .map(|x| format!("{}\t{}\t{}", x.1 .0, x.1 .1, x.0))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+    match x.is_empty() {
+        true => None,
+        false => Some(write_object(("tree", x.as_bytes())).to_string()),
     }
 }
 
 // This function takes the hash of the parent commit's tree, and
 // supplements it with changes from the index.
 pub fn create_new_tree(x: Option<String>) -> Option<String> {
-    // Produce a tuple (parent_tree_hash, filepaths)
-    let x: (Option<String>, Vec<String>) = (x, get_index());
+    // Produce a tuple (parent_tree_hash, staged files)
+    let x: (Option<String>, Index) = (x, get_index());
     if x.1.is_empty() {
         return None;
     }
 
-    // Get mapping from hashes to filepaths from previous tree.
-    let x: (HashMap<String, String>, Vec<String>) = (get_tree(x.0.as_ref()), x.1);
+    // Removing every file still makes a commit, just with an empty tree
+    Some(apply_tree_changes((
+        x.0.as_ref(),
+        x.1.into_iter()
+            .map(|x| (x.0, x.1.map(|x| x.to_string())))
+            .collect(),
+    )))
+}
+
+// Writes a new version of tree x.0 (None for starting from nothing) with the changes x.1 applied,
+// returning the id of the new tree, which is empty if no files are left
+pub fn apply_tree_changes(x: (Option<&String>, Vec<TreeChange>)) -> String {
+    write_tree(x).unwrap_or_else(|| write_object(("tree", b"")).to_string())
+}
+
+// Rewrites a single "<kind>\t<hash>..." line of a commit or tree so that it points to the migrated
+// id of the referenced object. Lines that don't reference anything are returned untouched.
+fn migrate_reference(x: (&str, &IdMapping)) -> String {
+    let x: (Vec<String>, &IdMapping) = (x.0.splitn(3, '\t').map(String::from).collect(), x.1);
 
-    // This tuple is of format (old_tree, new_tree)
-    let x: (HashMap<String, String>, HashMap<String, String>) = (
+    // Commits point at their tree and parent commit, tags at their commit, trees point at blobs and
+    // other trees
+    let x: (Option<&str>, Vec<String>, &IdMapping) = (
+        match x.0.first().map(String::as_str) {
+            Some("tree") if x.0.len() > 1 => Some("tree"),
+            Some("parent" | "object") if x.0.len() > 1 => Some("commit"),
+            Some("blob") if x.0.len() > 1 => Some("blob"),
+            _ => None,
+        },
         x.0,
-        x.1.iter()
+        x.1,
+    );
+
+    if x.0.is_none() {
+        return x.1.join("\t");
+    }
+
+    // A reference to an object that doesn't exist keeps its old id
+    let x: (String, Vec<String>) = (
+        migrate_object((x.0.unwrap_or_default(), &x.1[1], x.2))
+            .map_or(x.1[1].clone(), |x| x.to_string()),
+        x.1.clone(),
+    );
+
+    [&x.1[..1], std::slice::from_ref(&x.0), &x.1[2..]]
+        .concat()
+        .join("\t")
+}
+
+// The id object x was migrated to, or x itself if it wasn't
+pub fn get_migrated_id(x: (&str, &IdMapping)) -> String {
+    x.1.borrow()
+        .get(x.0)
+        .map_or(x.0.to_string(), |x| x.to_string())
+}
+
+// Rewrites the old and new id of every entry in the reflog of reference x.0 to the ids the
+// commits were migrated to
+pub fn migrate_reflog(x: (&str, &IdMapping)) {
+    let x: (String, &IdMapping) = (format!(".grit/logs/{}", x.0), x.1);
+    let x: (String, String) = (
+        x.0.clone(),
+        fs::read_to_string(&x.0)
+            .unwrap_or_default()
+            .lines()
+            .zip(std::iter::repeat(x.1))
             .map(|x| {
-                let x = (
-                    x,
-                    fs::read_to_string(x).expect(&format!("Could not read file {x} from index")),
-                );
-                (x.0.to_string(), hash_string(&x.1))
+                x.0.splitn(3, ' ')
+                    .enumerate()
+                    .zip(std::iter::repeat(x.1))
+                    .map(|x| match x.0 .0 {
+                        0 | 1 => get_migrated_id((x.0 .1, x.1)),
+                        _ => x.0 .1.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" ")
             })
+            .map(|x| format!("{x}\n"))
             .collect(),
     );
 
-    // Combine the previous tree with the new data
-    let x =
-        x.0.into_iter()
-            .chain(x.1.into_iter())
-            .collect::<HashMap<String, String>>();
+    let _ = fs::write(x.0, x.1);
+}
 
-    // Create contents of new tree
-    let x: String = x
-        .iter()
-        .map(|x: (&String, &String)| format!("blob\t{}\t{}", x.1, x.0))
-        .collect::<Vec<String>>()
-        .join("\n");
+// Rewrites the object `x.1` of kind `x.0` (and, recursively, everything it references) so that
+// all ids within it are canonical and it's stored in the current format. Legacy objects don't
+// record their own kind, so it has to be inferred from the line that referenced them. Returns the
+// corrected id, or None if the object is missing or can't be read.
+pub fn migrate_object(x: (&str, &str, &IdMapping)) -> Option<ObjectId> {
+    if let Some(x) = x.2.borrow().get(x.1) {
+        return Some(*x);
+    }
 
-    let x: (String, String) = (x.clone(), hash_string(&x));
+    let x: (&str, &str, &IdMapping, (Option<String>, Vec<u8>)) =
+        (x.0, x.1, x.2, read_object_with_kind(x.1).ok().flatten()?);
+    let x: (String, &str, &IdMapping, Vec<u8>) =
+        (x.3 .0.unwrap_or(x.0.to_string()), x.1, x.2, x.3 .1);
 
-    let _ = write!(
-        File::create(format!(".grit/{}", x.1)).expect("Could not create tree object"),
-        "{}",
-        x.0
+    // Blobs are kept as-is. For commits and tags only the header (up to the first empty line)
+    // contains references, for trees every line does.
+    let x: (String, &str, &IdMapping, Vec<u8>) = (
+        x.0.clone(),
+        x.1,
+        x.2,
+        match x.0.as_str() {
+            "blob" => x.3,
+            _ => {
+                let x: ((String, String), &IdMapping) = (
+                    match x.0.as_str() {
+                        "commit" | "tag" => String::from_utf8_lossy(&x.3)
+                            .split_once("\n\n")
+                            .map(|x| (x.0.to_string(), format!("\n\n{}", x.1)))
+                            .unwrap_or_else(|| {
+                                (String::from_utf8_lossy(&x.3).to_string(), String::new())
+                            }),
+                        _ => (String::from_utf8_lossy(&x.3).to_string(), String::new()),
+                    },
+                    x.2,
+                );
+
+                format!(
+                    "{}{}",
+                    x.0 .0
+                        .split('\n')
+                        .zip(std::iter::repeat(x.1))
+                        .map(migrate_reference)
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                    x.0 .1
+                )
+                .into_bytes()
+            }
+        },
     );
 
-    Some(x.1)
+    let _ = write_object((&x.0, &x.3));
+    let x: (&str, &IdMapping, ObjectId) = (x.1, x.2, hash_object((&x.0, &x.3)));
+    x.1.borrow_mut().insert(x.0.to_string(), x.2);
+
+    Some(x.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Objects get the same ids git gives them
+    #[test]
+    fn hash_object_matches_git() {
+        assert_eq!(
+            hash_object(("blob", b"hello\n")).to_string(),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+        assert_eq!(
+            hash_object(("tree", b"")).to_string(),
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+        );
+    }
+
+    #[test]
+    fn parse_object_header_rejects_size_mismatch() {
+        assert_eq!(
+            parse_object_header((b"blob 2\0hi".to_vec(), "x")),
+            Ok((String::from("blob"), b"hi".to_vec()))
+        );
+        assert!(parse_object_header((b"blob 5\0hi".to_vec(), "x")).is_err());
+        assert!(parse_object_header((b"blob 2hi".to_vec(), "x")).is_err());
+        assert!(parse_object_header((b"nope 2\0hi".to_vec(), "x")).is_err());
+    }
+
+    #[test]
+    fn decode_object_reads_current_and_legacy_objects() {
+        assert_eq!(
+            decode_object((compress_object(b"blob 6\0hello\n"), "x", true)),
+            Ok((Some(String::from("blob")), b"hello\n".to_vec()))
+        );
+
+        // Legacy objects are stored directly in .grit as they are, without a kind
+        assert_eq!(
+            decode_object((b"legacy contents".to_vec(), "x", false)),
+            Ok((None, b"legacy contents".to_vec()))
+        );
+        assert!(decode_object((b"legacy contents".to_vec(), "x", true)).is_err());
+
+        // Legacy contents that happen to be zlib data are still legacy contents
+        assert_eq!(
+            decode_object((compress_object(b"no header"), "x", false)),
+            Ok((None, compress_object(b"no header")))
+        );
+    }
+
+    // A broken header is an error rather than a panic
+    #[test]
+    fn decode_object_rejects_broken_headers() {
+        assert!(decode_object((compress_object(b"blob 9\0hi"), "x", true)).is_err());
+        assert!(decode_object((compress_object(b"no header"), "x", true)).is_err());
+        assert!(decode_object((compress_object(b"nope 2\0hi"), "x", true)).is_err());
+    }
+
+    // An empty revision means HEAD, and only the first colon separates the path
+    #[test]
+    fn split_revision_path_defaults_to_head() {
+        assert_eq!(split_revision_path("HEAD~1:a/b"), Some(("HEAD~1", "a/b")));
+        assert_eq!(split_revision_path(":a"), Some(("HEAD", "a")));
+        assert_eq!(split_revision_path("main:a:b"), Some(("main", "a:b")));
+        assert_eq!(split_revision_path("main:"), Some(("main", "")));
+        assert_eq!(split_revision_path("main@{1}"), None);
+    }
+
+    // Dates are shown in the timezone of their offset
+    #[test]
+    fn format_date_uses_the_offset() {
+        assert_eq!(
+            format_date("1700000000 +0100").as_deref(),
+            Some("Tue Nov 14 23:13:20 2023 +0100")
+        );
+        assert_eq!(
+            format_date("1700000000 +0230").as_deref(),
+            Some("Wed Nov 15 00:43:20 2023 +0230")
+        );
+        assert_eq!(
+            format_date("1699000000 -0500").as_deref(),
+            Some("Fri Nov 3 03:26:40 2023 -0500")
+        );
+        assert_eq!(
+            format_date("-1 +0000").as_deref(),
+            Some("Wed Dec 31 23:59:59 1969 +0000")
+        );
+        assert_eq!(
+            format_date("951782400 +0000").as_deref(),
+            Some("Tue Feb 29 00:00:00 2000 +0000")
+        );
+        assert_eq!(format_date("1700000000"), None);
+        assert_eq!(format_date("1700000000 +01"), None);
+        assert_eq!(format_date("x +0100"), None);
+    }
 }
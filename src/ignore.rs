@@ -0,0 +1,288 @@
+use std::env;
+use std::fs;
+
+use crate::helpers;
+
+// A single pattern from one of the ignore files
+#[derive(Clone)]
+pub struct Rule {
+    // The file the rule was read from, its line number and the line itself
+    pub source: String,
+    pub line: usize,
+    pub pattern: String,
+    pub negated: bool,
+    directory_only: bool,
+    // The directory of the .gritignore file, the rule only applies to paths below it
+    base: String,
+    glob: String,
+}
+
+impl Rule {
+    // Checks whether the path x.0 (a directory if x.1) matches the rule
+    fn matches(&self, x: (&str, bool)) -> bool {
+        (!self.directory_only || x.1)
+            && match self.base.is_empty() {
+                true => Some(x.0),
+                false => {
+                    x.0.strip_prefix(self.base.as_str())
+                        .and_then(|x| x.strip_prefix('/'))
+                }
+            }
+            .is_some_and(|x| helpers::glob_match((self.glob.as_bytes(), x.as_bytes())))
+    }
+}
+
+// The global ignore file is $GRIT_IGNORE_FILE, or "grit/ignore" in the user's config directory
+fn get_global_ignore_file() -> Option<String> {
+    env::var("GRIT_IGNORE_FILE")
+        .ok()
+        .or_else(|| {
+            env::var("XDG_CONFIG_HOME")
+                .ok()
+                .map(|x| format!("{x}/grit/ignore"))
+        })
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|x| format!("{x}/.config/grit/ignore"))
+        })
+}
+
+// Parses line x.3 (numbered x.2) of ignore file x.0 that's located in directory x.1
+fn parse_rule(x: (&str, &str, usize, &str)) -> Option<Rule> {
+    let x: (&str, &str, usize, &str) = (x.0, x.1, x.2, x.3.trim_end_matches('\r'));
+    if x.3.is_empty() || x.3.starts_with('#') {
+        return None;
+    }
+
+    // Trailing spaces are dropped unless the last one is escaped with a backslash
+    let x: (&str, &str, usize, &str, String) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        match x.3.trim_end_matches(' ') {
+            x if x.ends_with('\\') && !x.ends_with("\\\\") => format!("{x} "),
+            x => x.to_string(),
+        },
+    );
+
+    // A leading `!` re-includes whatever the pattern matches, a trailing `/` only matches
+    // directories
+    let x: (&str, &str, usize, &str, String, bool, bool) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        x.4.strip_prefix('!').unwrap_or(&x.4).to_string(),
+        x.4.starts_with('!'),
+        x.4.ends_with('/') && !x.4.ends_with("\\/"),
+    );
+
+    let x: (&str, &str, usize, &str, String, bool, bool) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3,
+        x.4.trim_end_matches('/').to_string(),
+        x.5,
+        x.6,
+    );
+    if x.4.is_empty() {
+        return None;
+    }
+
+    Some(Rule {
+        source: x.0.to_string(),
+        line: x.2,
+        pattern: x.3.to_string(),
+        negated: x.5,
+        directory_only: x.6,
+        base: x.1.to_string(),
+        // Patterns with a slash anywhere but at the end are relative to the directory of the
+        // ignore file, anything else matches at any depth
+        glob: match x.4.contains('/') {
+            true => x.4.trim_start_matches('/').to_string(),
+            false => format!("**/{}", x.4),
+        },
+    })
+}
+
+// Reads every rule from the ignore file x.0 which applies to the directory x.1
+fn read_rules(x: (&str, &str)) -> Vec<Rule> {
+    let x: (&str, &str, String) = (x.0, x.1, fs::read_to_string(x.0).unwrap_or_default());
+
+    x.2.lines()
+        .enumerate()
+        .zip(std::iter::repeat((x.0, x.1)))
+        .filter_map(|x| parse_rule((x.1 .0, x.1 .1, x.0 .0 + 1, x.0 .1)))
+        .collect()
+}
+
+// The rules that apply to the paths in directory x.1, given the rules x.0 that apply to its parent
+// directory: those plus the rules of its own .gritignore, which take precedence
+pub fn add_directory_rules(x: (&[Rule], &str)) -> Vec<Rule> {
+    x.0.iter()
+        .cloned()
+        .chain(read_rules((&helpers::join_path((x.1, ".gritignore")), x.1)))
+        .collect()
+}
+
+// Every rule that can apply to the paths in directory x, from the lowest to the highest precedence:
+// the global ignore file, .grit/info/exclude, and the .gritignore of every directory from the root
+// of the repository down to x
+pub fn get_rules(x: &str) -> Vec<Rule> {
+    match x.is_empty() {
+        true => add_directory_rules((
+            &get_global_ignore_file()
+                .map(|x| read_rules((&x, "")))
+                .unwrap_or_default()
+                .into_iter()
+                .chain(read_rules((".grit/info/exclude", "")))
+                .collect::<Vec<Rule>>(),
+            "",
+        )),
+        false => add_directory_rules((&get_rules(get_parent(x)), x)),
+    }
+}
+
+fn get_parent(x: &str) -> &str {
+    x.rsplit_once('/').map_or("", |x| x.0)
+}
+
+// Finds the last of the rules x.0 that matches path x.1 (a directory if x.2), without looking at
+// whether any of the directories containing the path are ignored
+fn find_rule<'a>(x: (&'a [Rule], &str, bool)) -> Option<&'a Rule> {
+    x.0.iter()
+        .rev()
+        .zip(std::iter::repeat((x.1, x.2)))
+        .find(|x| x.0.matches(x.1))
+        .map(|x| x.0)
+}
+
+// Like is_ignored, but assumes that the directories containing the path are known not to be
+// ignored, and takes the rules x.0 of the directory the path is in. Used when walking the working
+// tree, where ignored directories are never entered and every ignore file is read only once.
+pub fn is_excluded(x: (&[Rule], &str, bool)) -> bool {
+    find_rule(x).is_some_and(|x| !x.negated)
+}
+
+// The rules that apply to the paths in a directory, along with the rule that ignores the directory
+// or one of the directories containing it, if any
+type DirectoryRules = (Vec<Rule>, Option<Rule>);
+
+// The rules of directory x. Nothing inside an ignored directory can be re-included, so the rule
+// that ignores a parent directory always wins.
+fn get_directory_rules(x: &str) -> DirectoryRules {
+    match x.is_empty() {
+        true => (get_rules(""), None),
+        false => {
+            let x: (&str, DirectoryRules) = (x, get_directory_rules(get_parent(x)));
+            let x: (&str, Option<Rule>, Vec<Rule>) = (
+                x.0,
+                x.1 .1.or_else(|| {
+                    find_rule((&x.1 .0, x.0, true))
+                        .filter(|x| !x.negated)
+                        .cloned()
+                }),
+                x.1 .0,
+            );
+
+            (add_directory_rules((&x.2, x.0)), x.1)
+        }
+    }
+}
+
+// The rule that decides whether path x.0 (a directory if x.1) is ignored, if any
+pub fn get_deciding_rule(x: (&str, bool)) -> Option<Rule> {
+    let x: ((&str, bool), DirectoryRules) = (x, get_directory_rules(get_parent(x.0)));
+
+    x.1 .1
+        .or_else(|| find_rule((&x.1 .0, x.0 .0, x.0 .1)).cloned())
+}
+
+pub fn is_ignored(x: (&str, bool)) -> bool {
+    get_deciding_rule(x).is_some_and(|x| !x.negated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The rules of an ignore file with the contents x.0 in directory x.1
+    fn parse_rules(x: (&str, &str)) -> Vec<Rule> {
+        x.0.lines()
+            .enumerate()
+            .zip(std::iter::repeat(x.1))
+            .filter_map(|x| parse_rule(("x", x.1, x.0 .0 + 1, x.0 .1)))
+            .collect()
+    }
+
+    #[test]
+    fn parse_rule_skips_comments_and_keeps_escaped_spaces() {
+        assert!(parse_rules(("# comment\n\n/\n", "")).is_empty());
+        assert!(is_excluded((&parse_rules(("a\\ \n", "")), "a ", false)));
+        assert!(!is_excluded((&parse_rules(("a  \n", "")), "a ", false)));
+        assert_eq!(parse_rules(("a\n\n*.o\r\n", ""))[1].line, 3);
+    }
+
+    // A later negated rule re-includes what an earlier rule ignored, and the other way around
+    #[test]
+    fn negated_rules_reinclude_paths() {
+        let x: Vec<Rule> = parse_rules(("*.log\n!keep.log\n", ""));
+        assert!(is_excluded((&x, "a.log", false)));
+        assert!(!is_excluded((&x, "keep.log", false)));
+        assert!(!is_excluded((&x, "d/keep.log", false)));
+        assert!(find_rule((&x, "keep.log", false)).is_some_and(|x| x.negated));
+
+        let x: Vec<Rule> = parse_rules(("!keep.log\n*.log\n", ""));
+        assert!(is_excluded((&x, "keep.log", false)));
+    }
+
+    // A slash at the start or in the middle anchors a pattern to the directory of its ignore file,
+    // otherwise it matches at any depth
+    #[test]
+    fn slashes_anchor_patterns() {
+        let x: Vec<Rule> = parse_rules(("/a\nb/c\nd\n", ""));
+        assert!(is_excluded((&x, "a", false)));
+        assert!(!is_excluded((&x, "x/a", false)));
+        assert!(is_excluded((&x, "b/c", false)));
+        assert!(!is_excluded((&x, "x/b/c", false)));
+        assert!(is_excluded((&x, "d", false)));
+        assert!(is_excluded((&x, "x/y/d", false)));
+
+        // Rules of a nested ignore file only apply below its directory
+        let x: Vec<Rule> = parse_rules(("/a\nd\n", "sub"));
+        assert!(is_excluded((&x, "sub/a", false)));
+        assert!(!is_excluded((&x, "sub/x/a", false)));
+        assert!(!is_excluded((&x, "a", false)));
+        assert!(is_excluded((&x, "sub/x/d", false)));
+        assert!(!is_excluded((&x, "d", false)));
+        assert!(!is_excluded((&x, "subd", false)));
+    }
+
+    #[test]
+    fn double_stars_match_any_number_of_directories() {
+        let x: Vec<Rule> = parse_rules(("a/**/b\n**/c/d\ne/**\n", ""));
+        assert!(is_excluded((&x, "a/b", false)));
+        assert!(is_excluded((&x, "a/x/y/b", false)));
+        assert!(!is_excluded((&x, "x/a/b", false)));
+        assert!(is_excluded((&x, "c/d", false)));
+        assert!(is_excluded((&x, "x/y/c/d", false)));
+        assert!(is_excluded((&x, "e/x", false)));
+        assert!(is_excluded((&x, "e/x/y", false)));
+        assert!(!is_excluded((&x, "e", true)));
+    }
+
+    // A trailing slash only matches directories
+    #[test]
+    fn directory_rules_skip_files() {
+        let x: Vec<Rule> = parse_rules(("build/\n/out/\n", ""));
+        assert!(is_excluded((&x, "build", true)));
+        assert!(is_excluded((&x, "x/build", true)));
+        assert!(!is_excluded((&x, "build", false)));
+        assert!(is_excluded((&x, "out", true)));
+        assert!(!is_excluded((&x, "out", false)));
+        assert!(!is_excluded((&x, "x/out", true)));
+    }
+}
@@ -1,22 +1,70 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
+use std::process::Command;
 
 use std::env;
 use std::fs;
 use std::fs::DirBuilder;
 
+mod config;
+mod diff;
 mod helpers;
+mod ignore;
+mod oplog;
 
 fn help() {
     println!("Usage:");
     println!("\tgrit init\t\t\tInitialize the current working directory as a grit repository.");
-    println!("\tgrit status\t\t\tShow information about current tree.");
-    println!("\tgrit add [FILEPATH]\t\tAdd file to index.");
-    println!("\tgrit commit [COMMIT_MESSAGE]\tCommit changes from the index.");
-    println!("\tgrit fuckgoback\t\t\tRevert to files from the previous commit and clear index.");
+    println!("\tgrit status [-s]\t\tShow staged, unstaged and untracked changes.");
+    println!("\tgrit add [PATHSPEC]...\t\tAdd files, directories or glob matches to index.");
+    println!("\tgrit add -A\t\t\tAdd every file in the working tree to index.");
+    println!("\tgrit add -f [PATHSPEC]...\tAdd files to index even if they are ignored.");
+    println!("\tgrit check-ignore [PATH]...\tShow which ignore rule matches each path.");
+    println!("\tgrit config KEY [VALUE]\t\tGet or set a config value such as user.name.");
+    println!("\tgrit commit [-m MESSAGE]... [-F FILE]\tCommit changes from the index.");
+    println!("\tgrit diff [--staged] [-U N] [REVISION]... [-- PATH...]\tShow line changes.");
+    println!("\tgrit log [OPTION]... [REVISION] [-- PATH...]\tShow the commit history.");
+    println!(
+        "\tgrit show [REVISION[:PATH]]\tShow a commit and its changes, or a file at a revision."
+    );
+    println!("\tgrit branch [NAME [START]]\tList branches or create one.");
+    println!("\tgrit branch -d|-D NAME\t\tDelete a branch.");
+    println!("\tgrit branch -m [OLD] NEW\t\tRename a branch.");
+    println!("\tgrit switch [-f] [-c NEW] BRANCH\tSwitch to a branch, updating the working tree.");
+    println!(
+        "\tgrit checkout [-f] [-b NEW] REVISION\tCheck out a branch or a commit (detached HEAD)."
+    );
+    println!("\tgrit tag [-l] [PATTERN]...\tList tags.");
+    println!("\tgrit tag [-a] [-m MESSAGE] NAME [REVISION]\tTag a commit.");
+    println!("\tgrit tag -d NAME...\t\tDelete tags.");
+    println!("\tgrit merge REVISION\t\tMerge a branch or commit into the current branch.");
+    println!("\tgrit merge --abort\t\tGive up on a merge with conflicts.");
+    println!("\tgrit rm [--cached] [PATHSPEC]...\tRemove files from the working tree and index.");
+    println!("\tgrit mv [SOURCE]... [DESTINATION]\tMove or rename files and directories.");
+    println!("\tgrit reset [--soft|--mixed|--hard] [-f] [REVISION]\tMove HEAD to a commit.");
+    println!(
+        "\tgrit fuckgoback [-f]\t\tUndo the last commit and its changes (reset --hard HEAD~1)."
+    );
+    println!("\tgrit stash [push] [-m MESSAGE] [-u]\tSave local changes and reset to HEAD.");
+    println!("\tgrit stash list|show [-p]|apply|pop|drop [STASH]\tManage saved changes.");
+    println!("\tgrit reflog [REF]\t\tShow where HEAD or a branch has been, newest first.");
+    println!("\tgrit undo\t\t\tUndo the last operation that changed branches, tags or the index.");
+    println!("\tgrit op log\t\t\tList the operations that changed the repository, newest first.");
+    println!("\tgrit op restore ID\t\tRestore the repository to how it was after an operation.");
+    println!(
+        "\tgrit migrate-ids\t\tRewrite all objects to use full-length ids and the current format."
+    );
+    println!("\tgrit migrate-objects\t\tMove objects stored directly in .grit to .grit/objects.");
+    println!("Revisions:");
+    println!(
+        "\tHEAD, @, BRANCH, TAG, ID\tA commit, ids can be shortened to at least 4 characters."
+    );
+    println!("\tREVISION~N, REVISION^N\t\tThe N-th ancestor or the N-th parent of a commit.");
+    println!("\tREF@{{N}}\t\t\t\tWhere a branch (or HEAD) was N movements ago.");
+    println!("\tREVISION:PATH\t\t\tA file or directory at a commit.");
 }
 
 fn check_initialized<F>(function: F)
@@ -28,16 +76,55 @@ where
         println!("Not a grit repository");
         return;
     }
+    helpers::set_up_refs();
     function();
 }
 
 fn initialize() {
     // Check that the .grit directory exists, create one if not
     let _ = DirBuilder::new().recursive(true).create(".grit");
+    helpers::set_up_refs();
 }
 
-fn status() {
-    let x: Option<String> = helpers::get_current_head();
+fn status(x: &[String]) {
+    // The short format is just the two status characters and the path of every changed file
+    if x.iter().any(|x| x == "-s" || x == "--short") {
+        helpers::get_changes()
+            .iter()
+            .for_each(|x| println!("{}{} {}", x.1 .0, x.1 .1, x.0));
+        return;
+    }
+
+    match helpers::get_head_ref() {
+        Some(x) => println!("On branch {}", x.trim_start_matches("refs/heads/")),
+        None => println!(
+            "HEAD detached at {}",
+            helpers::abbreviate(&helpers::read_raw_ref("HEAD").unwrap_or_default())
+        ),
+    }
+
+    // Repositories created before object ids were fixed-width or objects had a header have to be
+    // migrated first, so HEAD is read without requiring it to be a valid id
+    let x: Option<String> = helpers::read_raw_ref("HEAD");
+    if x.as_deref()
+        .is_some_and(|x| x.parse::<helpers::ObjectId>().is_err())
+    {
+        println!("HEAD is not a valid object id, run `grit migrate-ids` to fix the repository\n");
+    } else if let Some(Err(x)) = x.as_deref().map(helpers::read_object_with_kind) {
+        println!("Could not read HEAD: {x}\n");
+    } else if x
+        .as_deref()
+        .and_then(|x| helpers::read_object_with_kind(x).ok().flatten())
+        .is_some_and(|x| x.0.is_none())
+    {
+        println!("HEAD is in the legacy object format, run `grit migrate-ids` to convert it\n");
+    }
+    if !helpers::get_legacy_object_ids().is_empty() {
+        println!(
+            "Objects are stored directly in .grit, run `grit migrate-objects` to move them (and \
+             `grit migrate-ids` for those in the legacy format)\n"
+        );
+    }
     // Context switch: variable gets rebound
     {
         // Context switch: variable gets rebound
@@ -46,222 +133,3324 @@ fn status() {
             print!("{}", x);
         } // Context switch: variable is back to hash of the current HEAD
 
-        match x {
-            Some(ref x) => {
-                print!("[{x}]");
-                let x: String =
-                    helpers::get_commit_message(x).expect("Could not read previous commit message");
-                println!(" {x}")
-            }
-            _ => (),
+        if let Some(ref x) = x {
+            print!("[{x}]");
+            let x: String = helpers::read_commit(Some(x))
+                .expect("Could not read previous commit message")
+                .message;
+            // Lines after the first one are indented so the message stands out from the status
+            println!(
+                " {}",
+                x.lines()
+                    .enumerate()
+                    .map(|x| match x {
+                        (0, x) | (_, x @ "") => x.to_string(),
+                        (_, x) => format!("    {x}"),
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )
         };
         // ✨ print a newline ✨
         let x: &str = "\n";
         print!("{}", x);
     } // Context switch: variable is back to hash of the current HEAD
 
-    let x: Option<String> = helpers::get_tree_of_commit(x.as_ref());
-    let x: HashMap<String, String> = helpers::get_tree(x.as_ref());
+    let x: Vec<helpers::PathStatus> = helpers::get_changes();
+    if x.is_empty() {
+        println!("\nNothing to commit, working tree clean");
+        return;
+    }
+    if helpers::get_merge_head().is_some() {
+        println!(
+            "\nYou are in the middle of a merge, commit to conclude it or run `grit merge --abort`"
+        );
+    }
 
-    // Shadowing happens inside loop, but doesn't overwrite HashMap from outside scope
-    println!("\nFiles tracked by grit:");
-    x.iter().for_each(|(x, _)| println!("\t{x}"));
+    // Unmerged paths are listed on their own, the other sections pick the paths they're
+    // interested in based on their two status characters
+    print_section((
+        "Unmerged paths:",
+        x.iter()
+            .filter(|x| is_unmerged(x.1))
+            .map(|x| format!("{:<16}{}", describe_conflict(x.1), x.0))
+            .collect(),
+    ));
+    let x: Vec<helpers::PathStatus> = x.into_iter().filter(|x| !is_unmerged(x.1)).collect();
+    print_section((
+        "Changes to be committed:",
+        x.iter()
+            .filter(|x| matches!(x.1 .0, 'A' | 'M' | 'D' | 'R'))
+            .map(|x| format!("{:<12}{}", describe_change(x.1 .0), x.0))
+            .collect(),
+    ));
+    print_section((
+        "Changes not staged for commit:",
+        x.iter()
+            .filter(|x| x.1 .1 == 'M')
+            .map(|x| format!("{:<12}{}", describe_change(x.1 .1), x.0))
+            .collect(),
+    ));
+    print_section((
+        "Deleted files:",
+        x.iter()
+            .filter(|x| x.1 .1 == 'D')
+            .map(|x| format!("{:<12}{}", describe_change(x.1 .1), x.0))
+            .collect(),
+    ));
+    print_section((
+        "Untracked files:",
+        x.iter()
+            .filter(|x| x.1 .1 == '?')
+            .map(|x| x.0.to_string())
+            .collect(),
+    ));
+}
 
-    let x: Vec<String> = helpers::get_index();
+// Whether the two status characters x (see helpers::get_changes) belong to a path with a conflict
+fn is_unmerged(x: (char, char)) -> bool {
+    matches!(x, ('U', _) | (_, 'U') | ('A', 'A'))
+}
 
-    // Shadowing happens inside loop, but doesn't overwrite HashMap from outside scope
-    println!("\nChanges to be committed:");
-    x.iter().for_each(|x| println!("\t{x}"));
+fn describe_conflict(x: (char, char)) -> &'static str {
+    match x {
+        ('A', 'A') => "both added:",
+        ('D', _) => "deleted by us:",
+        (_, 'D') => "deleted by them:",
+        _ => "both modified:",
+    }
 }
 
-fn add(x: Option<&String>) {
+fn describe_change(x: char) -> &'static str {
     match x {
-        Some(x) => {
-            // Context switch: variable is rebound
-            {
-                let x: (&String, String) = (
-                    x,
-                    match fs::read_to_string(x) {
-                        Ok(x) => x,
-                        _ => {
-                            println!("{x} does not match any file.");
-                            return;
-                        }
-                    },
-                );
+        'A' => "new file:",
+        'D' => "deleted:",
+        'R' => "renamed:",
+        _ => "modified:",
+    }
+}
 
-                // This one is actually kind of disgusting. We need to compare the hash of the
-                // added file's contents to the hash of the same file in the
-                // current working tree. We can't use map_or etc. because that jumps into a new
-                // context where we no longer have access to the full tuple.
-                // Instead, just make a massive tuple and perform the check afterwards.
-                // NOTE: We have to do the read from the HashMap in two parts. Otherwise, compiler
-                // freaks out over variable lifetimes, probably due to the excessive use of as_ref?
-                let x: (&String, String, HashMap<String, String>) = (
-                    x.0,
-                    x.1,
-                    helpers::get_tree(
-                        helpers::get_tree_of_commit(helpers::get_current_head().as_ref()).as_ref(),
-                    ),
-                );
+// Prints the lines x.1 under the title x.0, or nothing at all if there are no lines
+fn print_section(x: (&str, Vec<String>)) {
+    if x.1.is_empty() {
+        return;
+    }
 
-                let x: (&String, String, Option<&String>) = (x.0, x.1, x.2.get(x.0));
+    println!("\n{}", x.0);
+    x.1.iter().for_each(|x| println!("\t{x}"));
+}
 
-                if x.2.is_some() && x.2.unwrap().to_string() == helpers::hash_string(&x.1) {
-                    println!("No changes to add...");
-                    return;
-                }
+// A file that was staged in the format (path, new hash or None if the file was deleted, whether
+// it's the same as in the current tree)
+type StagedFile = (String, helpers::Staged, bool);
 
-                let _ = write!(
-                    File::create(format!(".grit/{}", helpers::hash_string(&x.1)))
-                        .expect("Could not save added file"),
-                    "{}",
-                    x.1
-                );
-            } // Context switch: variable is back to the path of the file to be added
+// The index along with the paths that have conflicts
+type IndexState = (helpers::Index, BTreeSet<String>);
+
+// Stores the contents of a single file as a blob, given the state of the index x.1. If the file
+// differs from what is currently staged for it (or from the current tree if nothing is staged),
+// returns the path and the new hash (None if the file was deleted) along with whether it's the
+// same as in the current tree. Conflicted files are always staged, since that's what resolves
+// their conflict.
+fn stage(x: (String, &IndexState)) -> Option<StagedFile> {
+    // File contents are kept as raw bytes, so anything (images, binaries, text in whatever
+    // encoding) can be stored as a blob
+    let x: (String, &IndexState, Option<Vec<u8>>) = (x.0.clone(), x.1, fs::read(&x.0).ok());
+
+    // Look up the hash of the same file in the current working tree, only reading the subtrees
+    // along its path
+    let x: (
+        String,
+        &IndexState,
+        Option<Vec<u8>>,
+        helpers::Staged,
+        helpers::Staged,
+    ) = (
+        x.0.clone(),
+        x.1,
+        x.2.clone(),
+        x.2.as_ref().map(|x| helpers::hash_object(("blob", x))),
+        helpers::get_tree_path((
+            helpers::get_tree_of_commit(
+                helpers::get_current_head().map(|x| x.to_string()).as_ref(),
+            )
+            .as_ref(),
+            &x.0,
+        ))
+        .and_then(|x| x.parse().ok()),
+    );
+
+    if x.1 .0.get(&x.0).cloned().unwrap_or(x.4) == x.3 && !x.1 .1.contains(&x.0) {
+        return None;
+    }
+
+    if let Some(ref x) = x.2 {
+        let _ = helpers::write_object(("blob", x));
+    }
+    Some((x.0, x.3, x.4 == x.3))
+}
+
+fn add(x: &[String]) {
+    if x.is_empty() {
+        return help();
+    }
 
-            // Turn variable into tuple of the current index contents and the path of the file to
-            // be added; Use RefCell to wrap vector with an immutable variable
-            let x: (RefCell<Vec<String>>, &String) = (RefCell::new(helpers::get_index()), x);
+    // Ignored files are only added along with --force, which also makes directories and globs
+    // include the ignored files they contain
+    let x: (&[String], bool) = (x, x.iter().any(|x| x == "-f" || x == "--force"));
+    if !x.1 {
+        if let Some(x) = x.0.iter().map(|x| helpers::normalize_path(x)).find(|x| {
+            !x.is_empty() && ignore::is_ignored((x, Path::new(x).is_dir())) && Path::new(x).exists()
+        }) {
+            println!("{x} is ignored, use -f if you really want to add it.");
+            return;
+        }
+    }
 
-            if !x.0.borrow().contains(&x.1) {
-                x.0.borrow_mut().push(x.1.to_string());
+    // Staging everything is the same as staging the root of the repository
+    let x: (Vec<String>, bool) = (
+        match x.0.iter().any(|x| x == "-A" || x == "--all") {
+            true => vec![".".to_string()],
+            false => {
+                x.0.iter()
+                    .filter(|x| *x != "-f" && *x != "--force")
+                    .cloned()
+                    .collect()
             }
+        },
+        x.1,
+    );
 
-            let x: String = x.0.borrow().join("\n");
+    // Pair every pathspec with the files it expands to. Tracked files that were deleted from the
+    // working tree are matched as well, so that their removal gets staged. Only the part of the
+    // HEAD tree a pathspec can match is read.
+    let x: (Vec<String>, (Option<String>, helpers::Index, bool)) = (
+        x.0,
+        (
+            helpers::get_tree_of_commit(
+                helpers::get_current_head().map(|x| x.to_string()).as_ref(),
+            ),
+            helpers::get_index(),
+            x.1,
+        ),
+    );
+    let x: (Vec<(String, Vec<String>)>, bool) = (
+        x.0.iter()
+            .zip(std::iter::repeat(&x.1))
+            .map(|x| {
+                (
+                    x.0.clone(),
+                    helpers::expand_pathspec((x.0, x.1 .2))
+                        .into_iter()
+                        .chain(
+                            helpers::expand_tracked_pathspec((
+                                x.0,
+                                &helpers::apply_index((
+                                    helpers::get_tree_under((
+                                        x.1 .0.as_ref(),
+                                        &helpers::get_pathspec_directory(x.0),
+                                    )),
+                                    &x.1 .1,
+                                )),
+                            ))
+                            .into_iter()
+                            .filter(|x| !Path::new(x).exists()),
+                        )
+                        .collect(),
+                )
+            })
+            .collect(),
+        x.1 .2,
+    );
 
-            let _ = write!(
-                File::create(format!(".grit/index")).expect("Could not save added file"),
-                "{}",
-                x
+    // Globs can match nothing but ignored files, which are only left out unless adding is forced
+    if let Some(x) =
+        x.0.iter()
+            .zip(std::iter::repeat(x.1))
+            .find(|x| x.0 .1.is_empty())
+    {
+        match !x.1 && !helpers::expand_pathspec((&x.0 .0, true)).is_empty() {
+            true => println!(
+                "{} only matches ignored files, use -f if you really want to add them.",
+                x.0 .0
+            ),
+            false => println!("{} does not match any file.", x.0 .0),
+        }
+        return;
+    }
+
+    // Deduplicate files that were matched by several pathspecs, and only keep those that actually
+    // changed
+    let x: (BTreeSet<String>, IndexState) = (
+        x.0.into_iter().flat_map(|x| x.1).collect(),
+        (
+            helpers::get_index(),
+            helpers::get_conflicts().into_keys().collect(),
+        ),
+    );
+    let x: (Vec<StagedFile>, RefCell<helpers::Index>) = (
+        x.0.into_iter()
+            .zip(std::iter::repeat(&x.1))
+            .filter_map(stage)
+            .collect(),
+        RefCell::new(x.1 .0.clone()),
+    );
+
+    if x.0.is_empty() {
+        println!("No changes to add...");
+        return;
+    }
+
+    // Files that were changed back to how they are in the current tree have nothing to stage
+    x.0.iter().zip(std::iter::repeat(&x.1)).for_each(|x| {
+        match x.0 .2 {
+            true => x.1.borrow_mut().remove(&x.0 .0),
+            false => x.1.borrow_mut().insert(x.0 .0.clone(), x.0 .1),
+        };
+    });
+    helpers::write_index(&x.1.borrow());
+
+    // Files that are resolved to how they are in the current tree don't end up in the index, so
+    // their conflicts have to be cleared on their own
+    helpers::write_conflicts(
+        &helpers::get_conflicts()
+            .into_iter()
+            .zip(std::iter::repeat(
+                &x.0.iter()
+                    .map(|x| x.0.clone())
+                    .collect::<BTreeSet<String>>(),
+            ))
+            .filter(|x| !x.1.contains(&x.0 .0))
+            .map(|x| x.0)
+            .collect(),
+    );
+
+    println!("Staged {} file(s):", x.0.len());
+    x.0.iter().for_each(|x| match x.1 {
+        Some(_) => println!("\t{}", x.0),
+        None => println!("\t{} (deleted)", x.0),
+    });
+}
+
+// Checks whether removing a file would throw away work that isn't committed: x.0 is the path,
+// x.1 its hash in the HEAD tree, x.2 the hash staged for the next commit and x.3 whether the file
+// is kept in the working tree
+fn has_local_changes(x: (&String, Option<&String>, Option<&String>, bool)) -> bool {
+    let x: (Option<&String>, Option<&String>, bool, Option<String>) = (
+        x.1,
+        x.2,
+        x.3,
+        fs::read(x.0).ok().map(|x| helpers::hash_blob(&x)),
+    );
+
+    // Staged contents that differ from HEAD, and contents in the working tree that differ from
+    // the staged ones
+    let x: (bool, bool, bool) = (x.1 != x.0, x.3.is_some() && x.3.as_ref() != x.1, x.2);
+
+    match x.2 {
+        // When the file stays in the working tree, the staged contents are only lost if the
+        // working tree doesn't have them either
+        true => x.0 && x.1,
+        false => x.0 || x.1,
+    }
+}
+
+fn rm(x: &[String]) {
+    let x: (bool, bool, Vec<&String>) = (
+        x.iter().any(|x| x == "--cached"),
+        x.iter().any(|x| x == "-f" || x == "--force"),
+        x.iter().filter(|x| !x.starts_with('-')).collect(),
+    );
+
+    if x.2.is_empty() {
+        return help();
+    }
+
+    // Paths are matched against the tree the next commit would have, so that files which were
+    // already deleted from the working tree can be removed as well
+    let x: ((bool, bool), Vec<&String>, helpers::Tree, helpers::Index) = (
+        (x.0, x.1),
+        x.2,
+        helpers::get_head_tree(),
+        helpers::get_index(),
+    );
+    let x: (
+        (bool, bool),
+        Vec<&String>,
+        helpers::Tree,
+        helpers::Index,
+        helpers::Tree,
+    ) = (
+        x.0,
+        x.1,
+        x.2.clone(),
+        x.3.clone(),
+        helpers::apply_index((x.2, &x.3)),
+    );
+
+    if let Some(x) =
+        x.1.iter()
+            .zip(std::iter::repeat(&x.4))
+            .find(|x| helpers::expand_tracked_pathspec((x.0, x.1)).is_empty())
+    {
+        println!("{} does not match any tracked file.", x.0);
+        return;
+    }
+
+    let x: (
+        (bool, bool),
+        BTreeSet<String>,
+        helpers::Tree,
+        helpers::Index,
+        helpers::Tree,
+    ) = (
+        x.0,
+        x.1.iter()
+            .zip(std::iter::repeat(&x.4))
+            .flat_map(|x| helpers::expand_tracked_pathspec((x.0, x.1)))
+            .collect(),
+        x.2,
+        x.3,
+        x.4,
+    );
+
+    if !x.0 .1 {
+        if let Some(x) =
+            x.1.iter()
+                .zip(std::iter::repeat(&x))
+                .find(|x| has_local_changes((x.0, x.1 .2.get(x.0), x.1 .4.get(x.0), x.1 .0 .0)))
+        {
+            println!(
+                "{} has uncommitted changes, use --cached to keep the file or -f to remove it anyway.",
+                x.0
             );
+            return;
         }
-        _ => {
-            help();
+    }
+
+    let x: (
+        bool,
+        BTreeSet<String>,
+        helpers::Tree,
+        RefCell<helpers::Index>,
+    ) = (x.0 .0, x.1, x.2, RefCell::new(x.3));
+
+    x.1.iter().zip(std::iter::repeat(&x)).for_each(|x| {
+        // Files that aren't in HEAD only have to be dropped from the index
+        match x.1 .2.contains_key(x.0) {
+            true => x.1 .3.borrow_mut().insert(x.0.clone(), None),
+            false => x.1 .3.borrow_mut().remove(x.0),
+        };
+
+        if !x.1 .0 {
+            let _ = fs::remove_file(x.0);
+            helpers::remove_empty_parents(x.0);
+        }
+
+        println!("rm '{}'", x.0);
+    });
+
+    helpers::write_index(&x.3.borrow());
+}
+
+// Checks that the move x.0 -> x.1 can be made, given the tree of the next commit x.2 and whether
+// existing files may be overwritten (x.3). Returns the reason if it can't.
+fn check_move(x: (&String, &String, &helpers::Tree, bool)) -> Option<String> {
+    match x {
+        _ if helpers::expand_tracked_pathspec((x.0, x.2)).is_empty() => {
+            Some(format!("{} is not tracked", x.0))
+        }
+        _ if !Path::new(x.0).exists() => Some(format!("{} does not exist", x.0)),
+        _ if x.1 == x.0 || x.1.starts_with(&format!("{}/", x.0)) => {
+            Some(format!("can not move {} into itself", x.0))
+        }
+        _ if Path::new(x.1).exists() && !x.3 => Some(format!("{} already exists", x.1)),
+        _ => None,
+    }
+}
+
+fn mv(x: &[String]) {
+    let x: (bool, Vec<String>) = (
+        x.iter().any(|x| x == "-f" || x == "--force"),
+        x.iter()
+            .filter(|x| !x.starts_with('-'))
+            .map(|x| helpers::normalize_path(x))
+            .collect(),
+    );
+
+    if x.1.len() < 2 {
+        return help();
+    }
+
+    // Everything is moved to the last argument. Moving into an existing directory (which is the
+    // only option with several sources) keeps the names of the moved files.
+    let x: (bool, Vec<(String, String)>) = (
+        x.0,
+        x.1[..x.1.len() - 1]
+            .iter()
+            .zip(std::iter::repeat((&x.1[x.1.len() - 1], x.1.len() > 2)))
+            .map(|x| match x.1 .1 || Path::new(x.1 .0).is_dir() {
+                true => (
+                    x.0.clone(),
+                    helpers::join_path((x.1 .0, x.0.rsplit('/').next().unwrap_or_default())),
+                ),
+                false => (x.0.clone(), x.1 .0.clone()),
+            })
+            .collect(),
+    );
+
+    if x.1.len() > 1 && !Path::new(&x.1[0].1).parent().is_some_and(|x| x.is_dir()) {
+        println!("Destination is not a directory");
+        return;
+    }
+
+    let x: (
+        bool,
+        Vec<(String, String)>,
+        helpers::Tree,
+        RefCell<helpers::Index>,
+    ) = (
+        x.0,
+        x.1,
+        helpers::get_head_tree(),
+        RefCell::new(helpers::get_index()),
+    );
+
+    // Every move is checked before anything is touched
+    if let Some(x) = x.1.iter().zip(std::iter::repeat(&x)).find_map(|x| {
+        check_move((
+            &x.0 .0,
+            &x.0 .1,
+            &helpers::apply_index((x.1 .2.clone(), &x.1 .3.borrow())),
+            x.1 .0,
+        ))
+    }) {
+        println!("Can not move: {x}");
+        return;
+    }
+
+    x.1.iter().zip(std::iter::repeat(&x)).for_each(|x| {
+        if let Some(x) = Path::new(&x.0 .1).parent() {
+            let _ = fs::create_dir_all(x);
+        }
+        // A file that couldn't be moved keeps its entry in the index
+        if let Some(x) = fs::rename(&x.0 .0, &x.0 .1).err().zip(Some(x.0)) {
+            println!("Could not move {} to {}: {}", x.1 .0, x.1 .1, x.0);
             return;
         }
+        helpers::remove_empty_parents(&x.0 .0);
+
+        // Every tracked file below the source keeps its staged contents under its new path. The
+        // old path is removed, which only has to be staged if it exists in HEAD.
+        let x: (BTreeSet<String>, &(&(String, String), _)) = (
+            helpers::expand_tracked_pathspec((
+                &x.0 .0,
+                &helpers::apply_index((x.1 .2.clone(), &x.1 .3.borrow())),
+            )),
+            &x,
+        );
+
+        x.0.iter().zip(std::iter::repeat(*x.1)).for_each(|x| {
+            let x: (&String, String, &helpers::Tree, &RefCell<helpers::Index>) = (
+                x.0,
+                format!("{}{}", x.1 .0 .1, &x.0[x.1 .0 .0.len()..]),
+                &x.1 .1 .2,
+                &x.1 .1 .3,
+            );
+
+            // What is staged for the file, or what it is in HEAD if nothing is
+            let x: (
+                &String,
+                String,
+                bool,
+                &RefCell<helpers::Index>,
+                helpers::Staged,
+            ) = (
+                x.0,
+                x.1,
+                x.2.contains_key(x.0),
+                x.3,
+                x.3.borrow()
+                    .get(x.0)
+                    .cloned()
+                    .unwrap_or_else(|| x.2.get(x.0).and_then(|x| x.parse().ok())),
+            );
+
+            let _ = match x.2 {
+                true => x.3.borrow_mut().insert(x.0.clone(), None),
+                false => x.3.borrow_mut().remove(x.0),
+            };
+            let _ = x.3.borrow_mut().insert(x.1, x.4);
+        });
+
+        println!("Renamed {} -> {}", x.1 .0 .0, x.1 .0 .1);
+    });
+
+    helpers::write_index(&x.3.borrow());
+}
+
+// Splits the arguments of commit into the messages given with -m (or as plain arguments) and the
+// files given with -F
+fn parse_commit_args(x: &[String]) -> Result<(Vec<String>, Vec<String>), String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok((Vec::new(), Vec::new())),
+        Some("-m" | "--message" | "-F" | "--file") if x.1.is_none() => Err(format!(
+            "Option {} requires a value",
+            x.0.unwrap_or_default()
+        )),
+        Some("-m" | "--message") => {
+            let x: (String, (Vec<String>, Vec<String>)) =
+                (x.1.cloned().unwrap_or_default(), parse_commit_args(x.2)?);
+            Ok(([vec![x.0], x.1 .0].concat(), x.1 .1))
+        }
+        Some("-F" | "--file") => {
+            let x: (String, (Vec<String>, Vec<String>)) =
+                (x.1.cloned().unwrap_or_default(), parse_commit_args(x.2)?);
+            Ok((x.1 .0, [vec![x.0], x.1 .1].concat()))
+        }
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, (Vec<String>, Vec<String>)) =
+                (x.0.unwrap_or_default().to_string(), parse_commit_args(x.3)?);
+            Ok(([vec![x.0], x.1 .0].concat(), x.1 .1))
+        }
     }
 }
 
-fn commit(x: Option<&String>) {
-    // The variable gets bound (by the match statement) to the path where the message should be
-    // written, along with the message (which we get as a parameter)
-    let x: (Option<&String>, (String, String)) = (
-        x,
-        match x {
-            Some(_) => {
-                // Bind x to the hash of the current HEAD and the contents of the new commit object
-                let x: (Option<String>, Option<String>) =
-                    (helpers::get_current_head(), helpers::get_current_head());
-                let x: (Option<String>, Option<String>) =
-                    (helpers::get_tree_of_commit(x.0.as_ref()), x.1);
-                let x: (Option<String>, Option<String>) = (helpers::create_new_tree(x.0), x.1);
-                if x.0.is_none() {
-                    println!("Nothing to commit...");
-                    return;
-                }
-                let x: (String, Option<String>) = (x.0.unwrap(), x.1);
-
-                // The message is written only when returning from this scope
-                let x = if x.1.is_some() {
-                    format!("tree\t{}\nparent\t{}\n\n", x.0, x.1.unwrap())
-                } else {
-                    format!("tree\t{}\n\n", x.0)
-                };
-
-                // Bind variable to a tuple of the hash of the new commit object and its contents
-                let x: (String, String) = (helpers::hash_string(&x), x);
-
-                let _ = write!(
-                    File::create(format!(".grit/HEAD")).expect("Could not open commit file"),
-                    "{}",
-                    x.0
-                );
+// The message given on the command line or read from the file given with -F ("-" reads standard
+// input). None if there is no message, in which case it has to be written in the editor.
+fn get_message_from_args(x: &[String]) -> Result<Option<String>, String> {
+    combine_messages(parse_commit_args(x)?)
+}
+
+// Combines the messages x.0 given with -m (every one becomes its own paragraph) and the files x.1
+// given with -F into a single message
+fn combine_messages(x: (Vec<String>, Vec<String>)) -> Result<Option<String>, String> {
+    match (x.0.is_empty(), x.1.as_slice()) {
+        (_, [_, _, ..]) => Err(String::from("Only one -F option can be given")),
+        (false, [_]) => Err(String::from("Options -m and -F cannot be used together")),
+        (false, []) => Ok(Some(x.0.join("\n\n"))),
+        (true, [x]) if x == "-" => std::io::read_to_string(std::io::stdin())
+            .map(Some)
+            .map_err(|_| String::from("Could not read the message from standard input")),
+        (true, [x]) => fs::read_to_string(x)
+            .map(Some)
+            .map_err(|_| format!("Could not read message file {x}")),
+        (true, []) => Ok(None),
+    }
+}
 
-                // Empty the index
-                let _ = File::create(format!(".grit/index")).expect("Could not open index file");
+// The template the editor opens with when committing, listing the staged changes
+fn get_commit_template() -> String {
+    format!(
+        "{}\n# Please enter the commit message for your changes. Lines starting\n\
+         # with '#' will be ignored, and an empty message aborts the commit.\n\
+         #\n# Changes to be committed:\n{}",
+        // The message of a merge is already filled in
+        fs::read_to_string(".grit/MERGE_MSG").unwrap_or_default(),
+        helpers::get_changes()
+            .iter()
+            .filter(|x| matches!(x.1 .0, 'A' | 'M' | 'D' | 'R'))
+            .map(|x| format!("#\t{:<12}{}\n", describe_change(x.1 .0), x.0))
+            .collect::<String>()
+    )
+}
 
-                // Return the path to the commit object along with its contents (without the commit
-                // message which we can't access in this scope)
-                x
-            }
-            _ => {
-                help();
-                return;
-            }
-        },
+// Writes the template x.1 to the file x.0 in .grit, opens it in $GRIT_EDITOR (or $EDITOR, or vi)
+// and returns whatever the user saved
+fn edit_message(x: (&str, String)) -> Result<String, String> {
+    let _ = fs::write(format!(".grit/{}", x.0), x.1);
+
+    let x: (&str, String) = (
+        x.0,
+        env::var("GRIT_EDITOR")
+            .ok()
+            .filter(|x| !x.trim().is_empty())
+            .or_else(|| env::var("EDITOR").ok().filter(|x| !x.trim().is_empty()))
+            .unwrap_or_else(|| String::from("vi")),
     );
 
-    // Finally write the commit object contents (adding the commit message that the variable
-    // contains again in this scope)
-    let _ = write!(
-        File::create(format!(".grit/{}", x.1 .0)).expect("Could not open commit file"),
-        "{}",
-        format!("{}{}", x.1 .1, x.0.unwrap_or(&"".to_string()))
+    // The editor goes through the shell, so it can be given with arguments (e.g. "code --wait")
+    let x: (&str, String, bool) = (
+        x.0,
+        x.1.clone(),
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", x.1))
+            .arg(&x.1)
+            .arg(format!(".grit/{}", x.0))
+            .status()
+            .is_ok_and(|x| x.success()),
     );
+
+    match x.2 {
+        true => fs::read_to_string(format!(".grit/{}", x.0))
+            .map_err(|_| format!("Could not read .grit/{}", x.0)),
+        false => Err(format!("There was a problem with the editor '{}'", x.1)),
+    }
 }
 
-fn fuckgoback() {
-    let x: Option<String> = helpers::get_current_head();
-    if x.is_none() {
-        println!("Could not read current HEAD");
+// Drops trailing whitespace and leading and trailing empty lines from message x.0, along with
+// every line starting with `#` if x.1
+fn clean_message(x: (&str, bool)) -> String {
+    x.0.lines()
+        .zip(std::iter::repeat(x.1))
+        .filter(|x| !(x.1 && x.0.starts_with('#')))
+        .map(|x| x.0.trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+fn commit(x: &[String]) {
+    let x: Result<Option<String>, String> = get_message_from_args(x);
+    if let Err(x) = x {
+        println!("{x}");
         return;
     }
 
-    let x = helpers::get_parent_of_commit(x.as_ref());
-    match x {
-        Some(x) => {
-            let _ = write!(
-                File::create(format!(".grit/HEAD")).expect("Could not open HEAD file"),
-                "{}",
-                x
-            );
+    // A merge can only be concluded once all of its conflicts are resolved
+    if let Some(x) = Some(helpers::get_conflicts()).filter(|x| !x.is_empty()) {
+        println!("Committing is not possible because you have unmerged files:");
+        x.keys().for_each(|x| println!("\t{x}"));
+        println!("Fix them up in the working tree, and then use `grit add <file>...`");
+        return;
+    }
 
-            let x: Option<String> = helpers::get_tree_of_commit(Some(&x));
-            let x: HashMap<String, String> = helpers::get_tree(x.as_ref());
+    // The variable gets bound to the message (None if it still has to be written in the editor)
+    // along with the new commit, which still lacks its message
+    let x: (Option<String>, helpers::Commit) = (x.unwrap_or_default(), {
+        // Bind x to the hash of the current HEAD and the contents of the new commit object
+        let x: (Option<String>, Option<String>) = (
+            helpers::get_current_head().map(|x| x.to_string()),
+            helpers::get_current_head().map(|x| x.to_string()),
+        );
+        let x: (Option<String>, Option<String>) = (helpers::get_tree_of_commit(x.0.as_ref()), x.1);
 
-            x.iter().for_each(|x| {
-                // classic (filepath, hash) -> (filepath, file contents) redefinition, you love to
-                // see it
-                let x = (
-                    x.0,
-                    fs::read_to_string(format!(".grit/{}", x.1))
-                        .expect(&format!("Could not open object {} from previous tree", x.1)),
-                );
+        // Who made the commit has to be known before anything gets written
+        let x: (
+            Option<String>,
+            Option<String>,
+            Result<helpers::Signatures, String>,
+        ) = (
+            x.0,
+            x.1,
+            helpers::get_signature("AUTHOR")
+                .and_then(|x| Ok((x, helpers::get_signature("COMMITTER")?))),
+        );
+        if let Err(x) = x.2 {
+            println!("{x}");
+            return;
+        }
 
-                let _ = write!(
-                    File::create(x.0)
-                        .expect(&format!("Could not open file {} from previous tree", x.1)),
-                    "{}",
-                    x.1
-                );
-            });
+        // Concluding a merge makes a commit even if the merge didn't change anything
+        let x: (Option<String>, Option<String>, helpers::Signatures) = (
+            helpers::create_new_tree(x.0.clone())
+                .or(x.0.filter(|_| helpers::get_merge_head().is_some())),
+            x.1,
+            x.2.unwrap_or_default(),
+        );
+        if x.0.is_none() {
+            println!("Nothing to commit...");
+            return;
         }
-        _ => {
-            println!("No previous commit");
+        let x: (String, Option<String>, helpers::Signatures) = (x.0.unwrap(), x.1, x.2);
+
+        // The message is added only when leaving this scope. The commit being merged is the
+        // second parent.
+        helpers::Commit {
+            tree: x.0,
+            parents: x.1.into_iter().chain(helpers::get_merge_head()).collect(),
+            headers: vec![
+                (String::from("author"), x.2 .0),
+                (String::from("committer"), x.2 .1),
+            ],
+            message: String::new(),
+        }
+    });
+
+    // Without a message on the command line, the user writes one in the editor. Comments are only
+    // stripped from what comes back from the editor.
+    let x: (Result<String, String>, helpers::Commit) = (
+        match x.0 {
+            Some(x) => Ok(clean_message((&x, false))),
+            None => edit_message(("COMMIT_EDITMSG", get_commit_template()))
+                .map(|x| clean_message((&x, true))),
+        },
+        x.1,
+    );
+    let x: (String, helpers::Commit) = match x {
+        (Err(x), _) => {
+            println!("{x}");
+            return;
+        }
+        (Ok(x), _) if x.is_empty() => {
+            println!("Aborting commit due to empty commit message");
             return;
         }
+        x => (x.0.unwrap_or_default(), x.1),
+    };
+
+    // Finally write the commit object (adding the commit message that the variable contains again
+    // in this scope), so that its hash covers the message as well
+    let x: (String, String) = (
+        helpers::write_object((
+            "commit",
+            helpers::Commit {
+                message: x.0.clone(),
+                ..x.1.clone()
+            }
+            .to_string()
+            .as_bytes(),
+        ))
+        .to_string(),
+        format!(
+            "commit{}: {}",
+            match x.1.parents.len() {
+                0 => " (initial)",
+                1 => "",
+                _ => " (merge)",
+            },
+            x.0.lines().next().unwrap_or_default()
+        ),
+    );
+
+    // The current branch moves forward to the new commit, which concludes any merge
+    helpers::update_head((&x.0, &x.1));
+    let _ = fs::remove_file(".grit/MERGE_HEAD");
+    let _ = fs::remove_file(".grit/MERGE_MSG");
+
+    // Empty the index
+    let _ = File::create(".grit/index").expect("Could not open index file");
+}
+
+// The options of grit log, parsed from its arguments
+#[derive(Default)]
+struct LogOptions {
+    oneline: bool,
+    count: Option<usize>,
+    format: Option<String>,
+    revision: Option<String>,
+    // Only commits that change at least one of these paths are shown
+    paths: Vec<String>,
+}
+
+fn parse_log_args(x: &[String]) -> Result<LogOptions, String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(LogOptions::default()),
+        Some("--") => Ok(LogOptions {
+            paths: x.3.to_vec(),
+            ..LogOptions::default()
+        }),
+        Some("--oneline" | "--pretty=oneline") => Ok(LogOptions {
+            oneline: true,
+            ..parse_log_args(x.3)?
+        }),
+        Some("-n") => Ok(LogOptions {
+            count: Some(
+                x.1.and_then(|x| x.parse::<usize>().ok())
+                    .ok_or("Option -n requires a number")?,
+            ),
+            ..parse_log_args(x.2)?
+        }),
+        // The count can also be given as "-n5" or "--max-count=5"
+        Some(_)
+            if x.0.is_some_and(|x| {
+                x.starts_with("--max-count=") || (x.starts_with("-n") && x.len() > 2)
+            }) =>
+        {
+            Ok(LogOptions {
+                count: Some(
+                    x.0.and_then(|x| {
+                        x.strip_prefix("--max-count=")
+                            .or_else(|| x.strip_prefix("-n"))
+                    })
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .ok_or("Option -n requires a number")?,
+                ),
+                ..parse_log_args(x.3)?
+            })
+        }
+        Some(_)
+            if x.0.is_some_and(|x| {
+                x.starts_with("--format=") || x.starts_with("--pretty=format:")
+            }) =>
+        {
+            Ok(LogOptions {
+                format: x.0.and_then(|x| {
+                    x.strip_prefix("--format=")
+                        .or_else(|| x.strip_prefix("--pretty=format:"))
+                        .map(String::from)
+                }),
+                ..parse_log_args(x.3)?
+            })
+        }
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => Ok(LogOptions {
+            revision: x.0.map(String::from),
+            ..parse_log_args(x.3)?
+        }),
     }
 }
 
-fn main() {
-    let x: i32 = 42;
+// The subject (first line) and body (everything after the first empty line) of commit x
+fn get_subject_and_body(x: &String) -> (String, String) {
+    let x: String = helpers::read_commit(Some(x))
+        .map(|x| x.message)
+        .unwrap_or_default();
 
-    let x: Vec<String> = env::args().collect();
-    let x: (Option<&String>, Option<&String>) = (x.get(1), x.get(2));
+    (
+        x.lines().next().unwrap_or_default().to_string(),
+        x.split_once("\n\n")
+            .map(|x| x.1.to_string())
+            .unwrap_or_default(),
+    )
+}
 
-    match x {
-        (Some(x), None) if x.as_str() == "init" => initialize(),
-        (Some(x), None) if x.as_str() == "status" => check_initialized(|| status()),
-        _ if x.0.is_some() && x.0.unwrap().as_str() == "add" && x.1.is_some() => {
-            check_initialized(|| add(x.1))
+// The value of the format placeholder at the start of x.0 (just after the `%`) for commit x.1,
+// along with the length of the placeholder. None if x.0 doesn't start with a known placeholder.
+fn get_placeholder(x: (&str, &String)) -> Option<(String, usize)> {
+    // Commits made before authors were recorded expand the author placeholders to nothing
+    let x: (&str, &String, helpers::Commit) = (
+        x.0,
+        x.1,
+        helpers::read_commit(Some(x.1)).unwrap_or_default(),
+    );
+    let x: (&str, &String, Option<helpers::ParsedSignature>, Vec<String>) = (
+        x.0,
+        x.1,
+        x.2.header("author")
+            .and_then(|x| helpers::parse_signature(x)),
+        x.2.parents,
+    );
+
+    match (x.0.get(..2), x.0.get(..1)) {
+        (Some("an"), _) => Some((x.2.map(|x| x.0).unwrap_or_default(), 2)),
+        (Some("ae"), _) => Some((x.2.map(|x| x.1).unwrap_or_default(), 2)),
+        (Some("ad"), _) => Some((
+            x.2.and_then(|x| helpers::format_date(&x.2))
+                .unwrap_or_default(),
+            2,
+        )),
+        (_, Some("H")) => Some((x.1.clone(), 1)),
+        (_, Some("h")) => Some((helpers::abbreviate(x.1), 1)),
+        (_, Some("P")) => Some((x.3.join(" "), 1)),
+        (_, Some("p")) => Some((
+            x.3.iter()
+                .map(|x| helpers::abbreviate(x))
+                .collect::<Vec<String>>()
+                .join(" "),
+            1,
+        )),
+        (_, Some("s")) => Some((get_subject_and_body(x.1).0, 1)),
+        (_, Some("b")) => Some((get_subject_and_body(x.1).1, 1)),
+        (_, Some("n")) => Some((String::from("\n"), 1)),
+        (_, Some("%")) => Some((String::from("%"), 1)),
+        _ => None,
+    }
+}
+
+// Replaces every placeholder in the template x.0 with its value for commit x.1. Unknown
+// placeholders are kept as they are.
+fn expand_format(x: (&str, &String)) -> String {
+    let x: (&str, Option<(&str, &str)>, &String) = (x.0, x.0.split_once('%'), x.1);
+    if x.1.is_none() {
+        return x.0.to_string();
+    }
+
+    let x: ((&str, &str), &String) = (x.1.unwrap_or_default(), x.2);
+    let x: (&str, &str, &String, Option<(String, usize)>) =
+        (x.0 .0, x.0 .1, x.1, get_placeholder((x.0 .1, x.1)));
+
+    format!(
+        "{}{}{}",
+        x.0,
+        x.3.as_ref().map_or("%", |x| x.0.as_str()),
+        expand_format((&x.1[x.3.as_ref().map_or(0, |x| x.1)..], x.2))
+    )
+}
+
+// Whether commit x.0 changes any of the paths x.1 compared to its parent
+fn touches_paths(x: (&String, &[String])) -> bool {
+    let x: (BTreeSet<String>, &[String]) = (
+        helpers::get_changed_paths((
+            helpers::get_tree_of_commit(Some(x.0)).as_ref(),
+            helpers::get_tree_of_commit(helpers::get_parent_of_commit(Some(x.0)).as_ref()).as_ref(),
+        )),
+        x.1,
+    );
+
+    x.1.iter().zip(std::iter::repeat(&x.0)).any(|x| {
+        x.1.iter()
+            .zip(std::iter::repeat(x.0))
+            .any(|x| helpers::matches_pathspec((x.1, x.0)))
+    })
+}
+
+// Prints commit x.0 in the format chosen by the options x.1, x.2 tells whether it's the first one
+fn print_commit(x: (&String, &LogOptions, bool)) {
+    if x.1.format.is_some() {
+        println!(
+            "{}",
+            expand_format((x.1.format.as_deref().unwrap_or_default(), x.0))
+        );
+        return;
+    }
+    if x.1.oneline {
+        println!(
+            "{} {}",
+            helpers::abbreviate(x.0),
+            get_subject_and_body(x.0).0
+        );
+        return;
+    }
+
+    if !x.2 {
+        println!();
+    }
+    println!("commit {}", x.0);
+    let x: helpers::Commit = helpers::read_commit(Some(x.0)).unwrap_or_default();
+    if x.parents.len() > 1 {
+        println!(
+            "Merge: {}",
+            x.parents
+                .iter()
+                .map(|x| helpers::abbreviate(x))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+    }
+    if let Some(x) = x.header("author").and_then(|x| helpers::parse_signature(x)) {
+        println!("Author: {} <{}>", x.0, x.1);
+        println!("Date:   {}", helpers::format_date(&x.2).unwrap_or(x.2));
+    }
+    println!();
+    x.message.lines().for_each(|x| match x {
+        "" => println!(),
+        x => println!("    {x}"),
+    });
+}
+
+fn log(x: &[String]) {
+    let x: LogOptions = match parse_log_args(x) {
+        Ok(x) => x,
+        Err(x) => {
+            println!("{x}");
+            return;
+        }
+    };
+
+    let x: (Result<String, String>, LogOptions) = (
+        helpers::resolve_revision(x.revision.as_deref().unwrap_or("HEAD")).map(|x| x.to_string()),
+        x,
+    );
+    match (&x.0, &x.1.revision) {
+        (Err(x), Some(_)) => {
+            println!("{x}");
+            return;
+        }
+        (Err(_), None) => {
+            println!("No commits yet");
+            return;
+        }
+        _ => {}
+    }
+
+    let x: (Vec<String>, LogOptions) = (
+        helpers::get_history(x.0.ok())
+            .into_iter()
+            .zip(std::iter::repeat(&x.1.paths))
+            .filter(|x| x.1.is_empty() || touches_paths((&x.0, x.1)))
+            .map(|x| x.0)
+            .take(x.1.count.unwrap_or(usize::MAX))
+            .collect(),
+        x.1,
+    );
+
+    x.0.iter()
+        .zip(std::iter::repeat(&x.1))
+        .enumerate()
+        .for_each(|x| print_commit((x.1 .0, x.1 .1, x.0 == 0)));
+}
+
+// The options of grit diff, parsed from its arguments
+#[derive(Default)]
+struct DiffOptions {
+    staged: bool,
+    context: Option<usize>,
+    revisions: Vec<String>,
+    paths: Vec<String>,
+}
+
+fn parse_diff_args(x: &[String]) -> Result<DiffOptions, String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(DiffOptions::default()),
+        Some("--") => Ok(DiffOptions {
+            paths: x.3.to_vec(),
+            ..DiffOptions::default()
+        }),
+        Some("--staged" | "--cached") => Ok(DiffOptions {
+            staged: true,
+            ..parse_diff_args(x.3)?
+        }),
+        Some("-U") => Ok(DiffOptions {
+            context: Some(
+                x.1.and_then(|x| x.parse::<usize>().ok())
+                    .ok_or("Option -U requires a number")?,
+            ),
+            ..parse_diff_args(x.2)?
+        }),
+        // The context can also be given as "-U5" or "--unified=5"
+        Some(_)
+            if x.0
+                .is_some_and(|x| x.starts_with("-U") || x.starts_with("--unified=")) =>
+        {
+            Ok(DiffOptions {
+                context: Some(
+                    x.0.and_then(|x| {
+                        x.strip_prefix("--unified=")
+                            .or_else(|| x.strip_prefix("-U"))
+                    })
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .ok_or("Option -U requires a number")?,
+                ),
+                ..parse_diff_args(x.3)?
+            })
         }
-        _ if x.0.is_some() && x.0.unwrap().as_str() == "commit" && x.1.is_some() => {
-            check_initialized(|| commit(x.1))
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, DiffOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_diff_args(x.3)?);
+            Ok(DiffOptions {
+                revisions: [vec![x.0], x.1.revisions].concat(),
+                ..x.1
+            })
         }
-        _ if x.0.is_some() && x.0.unwrap().as_str() == "fuckgoback" && x.1.is_none() => {
-            check_initialized(|| fuckgoback())
+    }
+}
+
+// The tree of the commit that revision x names
+fn get_revision_tree(x: &str) -> Result<helpers::Tree, String> {
+    helpers::resolve_revision(x)
+        .map(|x| helpers::get_tree(helpers::get_tree_of_commit(Some(&x.to_string())).as_ref()))
+}
+
+// The hashes of the files of the working tree that are tracked in any of the trees x
+fn get_working_hashes(x: &[&helpers::Tree]) -> helpers::Tree {
+    x.iter()
+        .flat_map(|x| x.keys())
+        .filter_map(|x| Some((x.clone(), helpers::hash_blob(&fs::read(x).ok()?))))
+        .collect()
+}
+
+// The two trees grit diff compares, and whether the new side is the working tree (so its contents
+// are read from the files rather than from the objects)
+fn get_diff_trees(x: &DiffOptions) -> Result<(helpers::Tree, helpers::Tree, bool), String> {
+    match (x.staged, x.revisions.len()) {
+        (_, 3..) => Err(String::from("Too many revisions")),
+        (true, 2) => Err(String::from("--staged takes at most one revision")),
+        (false, 2) => Ok((
+            get_revision_tree(&x.revisions[0])?,
+            get_revision_tree(&x.revisions[1])?,
+            false,
+        )),
+        // The index compared to HEAD or the given revision
+        (true, _) => Ok((
+            match x.revisions.first() {
+                Some(x) => get_revision_tree(x)?,
+                None => helpers::get_head_tree(),
+            },
+            helpers::apply_index((helpers::get_head_tree(), &helpers::get_index())),
+            false,
+        )),
+        // The working tree compared to the given revision
+        (false, 1) => {
+            let x: (helpers::Tree, helpers::Tree) = (
+                get_revision_tree(&x.revisions[0])?,
+                helpers::apply_index((helpers::get_head_tree(), &helpers::get_index())),
+            );
+            Ok((x.0.clone(), get_working_hashes(&[&x.0, &x.1]), true))
+        }
+        // The working tree compared to the index
+        (false, _) => {
+            let x: helpers::Tree =
+                helpers::apply_index((helpers::get_head_tree(), &helpers::get_index()));
+            Ok((x.clone(), get_working_hashes(&[&x]), true))
+        }
+    }
+}
+
+fn diff(x: &[String]) {
+    let x: DiffOptions = match parse_diff_args(x) {
+        Ok(x) => x,
+        Err(x) => {
+            println!("{x}");
+            return;
+        }
+    };
+
+    let x: (
+        Result<(helpers::Tree, helpers::Tree, bool), String>,
+        DiffOptions,
+    ) = (get_diff_trees(&x), x);
+    if let Err(ref x) = x.0 {
+        println!("{x}");
+        return;
+    }
+    let x: ((helpers::Tree, helpers::Tree, bool), DiffOptions) = (x.0.unwrap_or_default(), x.1);
+
+    print_diff((&x.0, &x.1));
+}
+
+// Prints the diff between the trees x.0 .0 and x.0 .1 (the working tree if x.0 .2), limited to the
+// paths and using the context given in the options x.1
+fn print_diff(x: (&(helpers::Tree, helpers::Tree, bool), &DiffOptions)) {
+    // Every path that differs between the two sides, limited to the given paths
+    x.0 .0
+        .keys()
+        .chain(x.0 .1.keys())
+        .collect::<BTreeSet<&String>>()
+        .into_iter()
+        .zip(std::iter::repeat(&x))
+        .filter(|x| x.1 .0 .0.get(x.0) != x.1 .0 .1.get(x.0))
+        .filter(|x| {
+            x.1 .1.paths.is_empty()
+                || x.1
+                     .1
+                    .paths
+                    .iter()
+                    .zip(std::iter::repeat(x.0))
+                    .any(|x| helpers::matches_pathspec((x.0, x.1)))
+        })
+        .for_each(|x| {
+            print!(
+                "{}",
+                diff::format_diff((
+                    x.0,
+                    x.1 .0
+                         .0
+                        .get(x.0)
+                        .and_then(|x| helpers::read_object(x))
+                        .as_deref(),
+                    match x.1 .0 .2 {
+                        true => fs::read(x.0).ok(),
+                        false => x.1 .0 .1.get(x.0).and_then(|x| helpers::read_object(x)),
+                    }
+                    .as_deref(),
+                    x.1 .1.context.unwrap_or(3),
+                ))
+            )
+        });
+}
+
+// Prints the file or directory that "<revision>:<path>" x names
+fn show_path(x: &str) {
+    // Files are printed as they are, whatever they contain
+    if let Some(x) = helpers::resolve_object(x)
+        .ok()
+        .and_then(|x| helpers::read_object_of_kind((&x.to_string(), &["blob"])))
+    {
+        let _ = std::io::stdout().write_all(&x);
+        return;
+    }
+
+    let x: (&str, (&str, &str)) = (x, helpers::split_revision_path(x).unwrap_or_default());
+    let x: (&str, Result<String, String>, String) = (
+        x.0,
+        helpers::resolve_revision(x.1 .0).map(|x| x.to_string()),
+        helpers::normalize_path(x.1 .1),
+    );
+    if let Err(x) = &x.1 {
+        println!("{x}");
+        return;
+    }
+    let x: (&str, Option<String>, String) =
+        (x.0, helpers::get_tree_of_commit(x.1.as_ref().ok()), x.2);
+
+    // Directories list the files and directories directly inside them. The root always exists,
+    // even when the tree is empty.
+    let x: (&str, bool, BTreeSet<String>) = (
+        x.0,
+        x.2.is_empty(),
+        helpers::get_tree(x.1.as_ref())
+            .into_keys()
+            .zip(std::iter::repeat(x.2))
+            .filter_map(|x| match x.1.is_empty() {
+                true => Some(x.0),
+                false => {
+                    x.0.strip_prefix(x.1.as_str())?
+                        .strip_prefix('/')
+                        .map(String::from)
+                }
+            })
+            .map(|x| match x.split_once('/') {
+                Some(x) => format!("{}/", x.0),
+                None => x,
+            })
+            .collect(),
+    );
+    if !x.1 && x.2.is_empty() {
+        println!("Path '{}' does not exist", x.0);
+        return;
+    }
+
+    println!("tree {}\n", x.0);
+    x.2.iter().for_each(|x| println!("{x}"));
+}
+
+// Prints the name, tagger and message of annotated tag object x
+fn print_tag(x: &String) {
+    println!(
+        "tag {}",
+        helpers::get_commit_header((Some(x), "tag")).unwrap_or_default()
+    );
+    if let Some(x) =
+        helpers::get_commit_header((Some(x), "tagger")).and_then(|x| helpers::parse_signature(&x))
+    {
+        println!("Tagger: {} <{}>", x.0, x.1);
+        println!("Date:   {}", helpers::format_date(&x.2).unwrap_or(x.2));
+    }
+    println!("\n{}\n", helpers::get_commit_message(x).unwrap_or_default());
+}
+
+fn show(x: &[String]) {
+    let x: &str = match x {
+        [] => "HEAD",
+        [x] => x,
+        _ => return help(),
+    };
+
+    // "<revision>:<path>" shows a single file or directory
+    if helpers::split_revision_path(x).is_some() {
+        show_path(x);
+        return;
+    }
+
+    let x: (&str, Result<String, String>) =
+        (x, helpers::resolve_revision(x).map(|x| x.to_string()));
+    if let Err(x) = &x.1 {
+        println!("{x}");
+        return;
+    }
+    let x: (&str, String) = (x.0, x.1.unwrap_or_default());
+
+    // Annotated tags are shown before the commit they point at (unless a branch with the same
+    // name takes precedence)
+    if let Some(x) = Some(x.0)
+        .filter(|x| helpers::is_valid_ref_name(x))
+        .filter(|x| !Path::new(&format!(".grit/refs/heads/{x}")).exists())
+        .and_then(|x| helpers::read_ref(&format!("refs/tags/{x}")))
+        .map(|x| x.to_string())
+        .filter(|x| helpers::get_commit_header((Some(x), "tag")).is_some())
+    {
+        print_tag(&x);
+    }
+
+    // The commit itself, followed by what it changed compared to its parent
+    print_commit((&x.1, &LogOptions::default(), true));
+    println!();
+    print_diff((
+        &(
+            helpers::get_tree(
+                helpers::get_tree_of_commit(helpers::get_parent_of_commit(Some(&x.1)).as_ref())
+                    .as_ref(),
+            ),
+            helpers::get_tree(helpers::get_tree_of_commit(Some(&x.1)).as_ref()),
+            false,
+        ),
+        &DiffOptions::default(),
+    ));
+}
+
+fn list_branches() {
+    if helpers::get_head_ref().is_none() {
+        println!(
+            "* (HEAD detached at {})",
+            helpers::abbreviate(&helpers::read_raw_ref("HEAD").unwrap_or_default())
+        );
+    }
+
+    helpers::get_branches()
+        .iter()
+        .zip(std::iter::repeat(helpers::get_head_ref()))
+        .for_each(|x| match x.1 == Some(format!("refs/heads/{}", x.0)) {
+            true => println!("* {}", x.0),
+            false => println!("  {}", x.0),
+        });
+}
+
+// Why branch x can't be created, if there's a reason. Branches are files, so a branch can't be
+// created where another one would have to be a directory or the other way around.
+fn check_new_branch(x: &str) -> Option<String> {
+    if !helpers::is_valid_ref_name(x) {
+        return Some(format!("'{x}' is not a valid branch name"));
+    }
+
+    helpers::get_branches()
+        .iter()
+        .zip(std::iter::repeat(x))
+        .find(|x| {
+            x.0 == x.1
+                || x.0.starts_with(&format!("{}/", x.1))
+                || x.1.starts_with(&format!("{}/", x.0))
+        })
+        .map(|x| match x.0 == x.1 {
+            true => format!("A branch named '{}' already exists", x.1),
+            false => format!("Cannot create '{}' because branch '{}' exists", x.1, x.0),
+        })
+}
+
+// Creates branch x.0 pointing at revision x.1 (HEAD if not given)
+fn create_branch(x: (&str, Option<&String>)) {
+    if let Some(x) = check_new_branch(x.0) {
+        println!("{x}");
+        return;
+    }
+
+    let x: (&str, Option<&String>, Result<String, String>) = (
+        x.0,
+        x.1,
+        helpers::resolve_revision(x.1.map_or("HEAD", |x| x.as_str())).map(|x| x.to_string()),
+    );
+    match x {
+        (_, Some(_), Err(x)) => println!("{x}"),
+        (_, None, Err(_)) => println!("No commits yet, there is nothing for a branch to point at"),
+        (_, _, Ok(_)) => {
+            helpers::update_ref((
+                &format!("refs/heads/{}", x.0),
+                &x.2.unwrap_or_default(),
+                &format!(
+                    "branch: Created from {}",
+                    x.1.map_or("HEAD", |x| x.as_str())
+                ),
+            ));
+            println!("Created branch {}", x.0);
+        }
+    }
+}
+
+// Removes the directories that were left empty below .grit/x.0 after deleting the reference x.1
+fn remove_empty_ref_directories(x: (&str, &str)) {
+    if let Some(x) = x.1.rsplit_once('/').zip(Some(x.0)) {
+        if fs::remove_dir(format!(".grit/{}/{}", x.1, x.0 .0)).is_ok() {
+            remove_empty_ref_directories((x.1, x.0 .0));
+        }
+    }
+}
+
+// Deletes branch x.0. Unless forced by x.1, only branches whose commits are all part of the
+// current branch's history can be deleted, so that no work gets lost.
+fn delete_branch(x: (&String, bool)) {
+    let x: (&String, bool, Option<String>) = (
+        x.0,
+        x.1,
+        helpers::read_raw_ref(&format!("refs/heads/{}", x.0)),
+    );
+    let x: (&String, bool, Option<String>, bool) = (
+        x.0,
+        x.1,
+        x.2.clone(),
+        x.2.is_some_and(|x| {
+            helpers::get_history(helpers::get_current_head().map(|x| x.to_string())).contains(&x)
+        }),
+    );
+
+    match x {
+        (x, _, None, _) => println!("Branch '{x}' not found"),
+        (x, _, _, _) if helpers::get_head_ref() == Some(format!("refs/heads/{x}")) => {
+            println!("Cannot delete branch '{x}' which you are currently on")
+        }
+        (x, false, _, false) => println!(
+            "Branch '{x}' is not fully merged, use `grit branch -D {x}` to delete it anyway"
+        ),
+        _ => {
+            let _ = fs::remove_file(format!(".grit/refs/heads/{}", x.0));
+            remove_empty_ref_directories(("refs/heads", x.0));
+            let _ = fs::remove_file(format!(".grit/logs/refs/heads/{}", x.0));
+            remove_empty_ref_directories(("logs/refs/heads", x.0));
+            println!(
+                "Deleted branch {} (was {})",
+                x.0,
+                helpers::abbreviate(&x.2.unwrap_or_default())
+            );
+        }
+    }
+}
+// Renames branch x.0 (the current branch if not given) to x.1
+fn rename_branch(x: (Option<&String>, &String)) {
+    let x: (Option<String>, &String) = (
+        x.0.cloned().or_else(|| {
+            helpers::get_head_ref().map(|x| x.trim_start_matches("refs/heads/").to_string())
+        }),
+        x.1,
+    );
+    if x.0.is_none() {
+        println!("HEAD is detached, there is no branch to rename");
+        return;
+    }
+
+    // The current branch can be renamed even before it has any commits
+    let x: (String, &String, bool) = (
+        x.0.clone().unwrap_or_default(),
+        x.1,
+        helpers::get_head_ref() == x.0.map(|x| format!("refs/heads/{x}")),
+    );
+    if !x.2 && !Path::new(&format!(".grit/refs/heads/{}", x.0)).is_file() {
+        println!("Branch '{}' not found", x.0);
+        return;
+    }
+    if let Some(x) = check_new_branch(x.1) {
+        println!("{x}");
+        return;
+    }
+
+    if let Some(x) = helpers::read_raw_ref(&format!("refs/heads/{}", x.0)).zip(Some(&x)) {
+        helpers::write_ref((&format!("refs/heads/{}", x.1 .1), &x.0));
+        let _ = fs::remove_file(format!(".grit/refs/heads/{}", x.1 .0));
+        remove_empty_ref_directories(("refs/heads", &x.1 .0));
+
+        // The reflog moves along with the branch
+        if let Some(x) = Path::new(&format!(".grit/logs/refs/heads/{}", x.1 .1)).parent() {
+            let _ = fs::create_dir_all(x);
+        }
+        let _ = fs::rename(
+            format!(".grit/logs/refs/heads/{}", x.1 .0),
+            format!(".grit/logs/refs/heads/{}", x.1 .1),
+        );
+        remove_empty_ref_directories(("logs/refs/heads", &x.1 .0));
+        helpers::append_reflog((
+            &format!("refs/heads/{}", x.1 .1),
+            Some(&x.0),
+            &x.0,
+            &format!(
+                "Branch: renamed refs/heads/{} to refs/heads/{}",
+                x.1 .0, x.1 .1
+            ),
+        ));
+    }
+    if x.2 {
+        helpers::set_head_ref(&format!("refs/heads/{}", x.1));
+    }
+
+    println!("Renamed branch {} to {}", x.0, x.1);
+}
+
+fn branch(x: &[String]) {
+    let x: (Option<&str>, &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x {
+        (None, _) | (Some("--list"), []) => list_branches(),
+        (Some("-d" | "--delete"), [x]) => delete_branch((x, false)),
+        (Some("-D"), [x]) => delete_branch((x, true)),
+        (Some("-m" | "--move"), [x]) => rename_branch((None, x)),
+        (Some("-m" | "--move"), [_, _]) => rename_branch((Some(&x.1[0]), &x.1[1])),
+        (Some(x), _) if x.starts_with('-') => help(),
+        (Some(_), []) => create_branch((x.0.unwrap_or_default(), None)),
+        (Some(_), [_]) => create_branch((x.0.unwrap_or_default(), Some(&x.1[0]))),
+        _ => help(),
+    }
+}
+
+// The options of grit switch and grit checkout, parsed from their arguments
+#[derive(Default)]
+struct SwitchOptions {
+    force: bool,
+    detach: bool,
+    // A new branch to create at the target and switch to
+    create: Option<String>,
+    target: Option<String>,
+}
+
+fn parse_switch_args(x: &[String]) -> Result<SwitchOptions, String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(SwitchOptions::default()),
+        Some("-f" | "--force" | "--discard-changes") => Ok(SwitchOptions {
+            force: true,
+            ..parse_switch_args(x.3)?
+        }),
+        Some("--detach") => Ok(SwitchOptions {
+            detach: true,
+            ..parse_switch_args(x.3)?
+        }),
+        Some("-c" | "--create" | "-b") => Ok(SwitchOptions {
+            create: Some(x.1.cloned().ok_or("Option -c requires a branch name")?),
+            ..parse_switch_args(x.2)?
+        }),
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, SwitchOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_switch_args(x.3)?);
+            match x.1.target {
+                Some(_) => Err(String::from("Only one branch or revision can be given")),
+                None => Ok(SwitchOptions {
+                    target: Some(x.0),
+                    ..x.1
+                }),
+            }
+        }
+    }
+}
+
+// The hash of the working tree file x, None if there's no such file
+fn get_working_hash(x: &str) -> Option<String> {
+    fs::read(x).ok().map(|x| helpers::hash_blob(&x))
+}
+
+// Whether something that isn't tracked in tree x.1 is in the way of writing file x.0: either an
+// untracked file where one of its directories should be, or a directory with untracked files in it
+// where the file should be
+fn is_blocked(x: (&str, &helpers::Tree)) -> bool {
+    Path::new(x.0)
+        .ancestors()
+        .skip(1)
+        .filter(|x| !x.as_os_str().is_empty())
+        .zip(std::iter::repeat(x.1))
+        .any(|x| x.0.is_file() && !x.1.contains_key(x.0.to_string_lossy().as_ref()))
+        || (Path::new(x.0).is_dir()
+            && helpers::get_working_tree_files(x.0)
+                .iter()
+                .zip(std::iter::repeat(x.1))
+                .any(|x| !x.1.contains_key(x.0)))
+}
+
+// Replaces the files of tree x.0 in the working tree with those of tree x.1. Files with local
+// changes (staged, modified, or untracked where the new tree has a file) are only touched when
+// forced with x.2, otherwise nothing happens at all if any of them would have to be.
+fn check_out_tree(x: (&helpers::Tree, &helpers::Tree, bool)) -> bool {
+    let x: (&helpers::Tree, &helpers::Tree, bool, helpers::Index) =
+        (x.0, x.1, x.2, helpers::get_index());
+
+    // Forcing resets every tracked or staged file, not only the ones that differ between the trees
+    let x: (
+        &helpers::Tree,
+        &helpers::Tree,
+        bool,
+        helpers::Index,
+        BTreeSet<String>,
+    ) = (
+        x.0,
+        x.1,
+        x.2,
+        x.3.clone(),
+        match x.2 {
+            true => {
+                x.0.keys()
+                    .chain(x.1.keys())
+                    .chain(x.3.keys())
+                    .cloned()
+                    .collect()
+            }
+            false => helpers::get_differing_paths((x.0, x.1)),
+        },
+    );
+
+    let x: (
+        &helpers::Tree,
+        &helpers::Tree,
+        bool,
+        BTreeSet<String>,
+        Vec<String>,
+    ) = (
+        x.0,
+        x.1,
+        x.2,
+        x.4.clone(),
+        x.4.iter()
+            .zip(std::iter::repeat(&x))
+            .filter(|x| {
+                x.1 .3.contains_key(x.0)
+                    || (get_working_hash(x.0).as_ref() != x.1 .0.get(x.0)
+                        && get_working_hash(x.0).as_ref() != x.1 .1.get(x.0))
+                    || (x.1 .1.contains_key(x.0) && is_blocked((x.0, x.1 .0)))
+            })
+            .map(|x| x.0.clone())
+            .collect(),
+    );
+    if !x.2 && !x.4.is_empty() {
+        println!("Your local changes to the following files would be overwritten:");
+        x.4.iter().for_each(|x| println!("\t{x}"));
+        println!("Commit them, or use --force to discard them");
+        return false;
+    }
+
+    // Files are removed first, so that they're out of the way of directories with the same name
+    x.3.iter()
+        .zip(std::iter::repeat(x.1))
+        .filter(|x| !x.1.contains_key(x.0))
+        .for_each(|x| {
+            let _ = fs::remove_file(x.0);
+            helpers::remove_empty_parents(x.0);
+        });
+    x.3.iter()
+        .zip(std::iter::repeat(x.1))
+        .filter(|x| x.1.contains_key(x.0) && get_working_hash(x.0).as_ref() != x.1.get(x.0))
+        .for_each(|x| {
+            if let Some(x) = Path::new(x.0).parent() {
+                let _ = fs::create_dir_all(x);
+            }
+            let _ = fs::write(
+                x.0,
+                x.1.get(x.0)
+                    .and_then(|x| helpers::read_object(x))
+                    .unwrap_or_else(|| panic!("Could not open object for {}", x.0)),
+            );
+        });
+
+    if x.2 {
+        helpers::write_index(&helpers::Index::new());
+    }
+    true
+}
+
+// Moves HEAD to the target of the options x.0, attaching it to the target if it's a branch and
+// detaching it otherwise. Targets that aren't branches need --detach, unless x.1 allows them
+// (which is the case for checkout).
+fn switch_to(x: (SwitchOptions, bool)) {
+    // The branch to end up on: either a new one, or the target if it's an existing branch
+    let x: (Option<String>, SwitchOptions, bool) = (
+        x.0.create.clone().or_else(|| {
+            x.0.target
+                .clone()
+                .filter(|_| !x.0.detach)
+                .filter(|x| helpers::is_valid_ref_name(x))
+                .filter(|x| Path::new(&format!(".grit/refs/heads/{x}")).is_file())
+        }),
+        x.0,
+        x.1,
+    );
+
+    if let Some(x) = x.1.create.as_deref().and_then(check_new_branch) {
+        println!("{x}");
+        return;
+    }
+    if x.0.is_none() && !x.1.detach && !x.2 {
+        match x.1.target {
+            Some(x) => println!("'{x}' is not a branch, use --detach to check out a commit"),
+            None => help(),
+        }
+        return;
+    }
+    if x.1.create.is_none()
+        && x.0.as_ref().map(|x| format!("refs/heads/{x}")) == helpers::get_head_ref()
+    {
+        println!("Already on '{}'", x.0.unwrap_or_default());
+        return;
+    }
+
+    let x: (Option<String>, Result<String, String>, SwitchOptions) = (
+        x.0,
+        helpers::resolve_revision(x.1.target.as_deref().unwrap_or("HEAD")).map(|x| x.to_string()),
+        x.1,
+    );
+    let x: (Option<String>, SwitchOptions, Result<String, String>) = (x.0, x.2, x.1);
+    match (&x.1.target, &x.2, &x.0) {
+        (Some(_), Err(x), _) => {
+            println!("{x}");
+            return;
+        }
+        // A new branch can be started before there are any commits, it just doesn't point at
+        // anything yet
+        (None, Err(_), Some(x)) => {
+            helpers::set_head_ref(&format!("refs/heads/{x}"));
+            println!("Switched to a new branch '{x}'");
+            return;
+        }
+        (None, Err(_), None) => {
+            println!("No commits yet, there is nothing to check out");
+            return;
+        }
+        _ => {}
+    }
+
+    if !check_out_tree((
+        &helpers::get_head_tree(),
+        &helpers::get_tree(helpers::get_tree_of_commit(x.2.as_ref().ok()).as_ref()),
+        x.1.force,
+    )) {
+        return;
+    }
+
+    // Where HEAD was, for the reflog: the branch name if on one, the commit otherwise
+    let x: (
+        Option<String>,
+        Option<String>,
+        String,
+        Option<String>,
+        String,
+    ) = (
+        x.0,
+        x.1.create
+            .as_ref()
+            .map(|_| x.1.target.clone().unwrap_or_else(|| String::from("HEAD"))),
+        x.2.unwrap_or_default(),
+        helpers::get_current_head().map(|x| x.to_string()),
+        helpers::get_head_ref().map_or_else(
+            || helpers::read_raw_ref("HEAD").unwrap_or_default(),
+            |x| x.trim_start_matches("refs/heads/").to_string(),
+        ),
+    );
+    if let Some(x) = x.1.as_ref().zip(x.0.as_ref()).zip(Some(&x.2)) {
+        helpers::update_ref((
+            &format!("refs/heads/{}", x.0 .1),
+            x.1,
+            &format!("branch: Created from {}", x.0 .0),
+        ));
+    }
+
+    match (x.0.clone(), x.1.is_some()) {
+        (Some(x), true) => {
+            helpers::set_head_ref(&format!("refs/heads/{x}"));
+            println!("Switched to a new branch '{x}'");
+        }
+        (Some(x), false) => {
+            helpers::set_head_ref(&format!("refs/heads/{x}"));
+            println!("Switched to branch '{x}'");
+        }
+        (None, _) => {
+            helpers::write_ref(("HEAD", &x.2));
+            println!(
+                "HEAD is now at {} {}",
+                helpers::abbreviate(&x.2),
+                get_subject_and_body(&x.2).0
+            );
+        }
+    }
+    helpers::append_reflog((
+        "HEAD",
+        x.3.as_deref(),
+        &x.2,
+        &format!(
+            "checkout: moving from {} to {}",
+            x.4,
+            x.0.as_deref().unwrap_or(&x.2)
+        ),
+    ));
+}
+
+fn switch(x: &[String]) {
+    match parse_switch_args(x) {
+        Ok(x) => switch_to((x, false)),
+        Err(x) => println!("{x}"),
+    }
+}
+
+fn checkout(x: &[String]) {
+    match parse_switch_args(x) {
+        Ok(x) => switch_to((x, true)),
+        Err(x) => println!("{x}"),
+    }
+}
+
+// The options of grit tag, parsed from its arguments
+#[derive(Default)]
+struct TagOptions {
+    list: bool,
+    delete: bool,
+    force: bool,
+    annotate: bool,
+    messages: Vec<String>,
+    files: Vec<String>,
+    // The name and revision of a new tag, the tags to delete, or the patterns to list
+    names: Vec<String>,
+}
+
+fn parse_tag_args(x: &[String]) -> Result<TagOptions, String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(TagOptions::default()),
+        Some("-l" | "--list") => Ok(TagOptions {
+            list: true,
+            ..parse_tag_args(x.3)?
+        }),
+        Some("-d" | "--delete") => Ok(TagOptions {
+            delete: true,
+            ..parse_tag_args(x.3)?
+        }),
+        Some("-f" | "--force") => Ok(TagOptions {
+            force: true,
+            ..parse_tag_args(x.3)?
+        }),
+        Some("-a" | "--annotate") => Ok(TagOptions {
+            annotate: true,
+            ..parse_tag_args(x.3)?
+        }),
+        Some("-m" | "--message" | "-F" | "--file") if x.1.is_none() => Err(format!(
+            "Option {} requires a value",
+            x.0.unwrap_or_default()
+        )),
+        // A message makes the tag annotated even without -a
+        Some("-m" | "--message") => {
+            let x: (String, TagOptions) = (x.1.cloned().unwrap_or_default(), parse_tag_args(x.2)?);
+            Ok(TagOptions {
+                annotate: true,
+                messages: [vec![x.0], x.1.messages].concat(),
+                ..x.1
+            })
+        }
+        Some("-F" | "--file") => {
+            let x: (String, TagOptions) = (x.1.cloned().unwrap_or_default(), parse_tag_args(x.2)?);
+            Ok(TagOptions {
+                annotate: true,
+                files: [vec![x.0], x.1.files].concat(),
+                ..x.1
+            })
+        }
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, TagOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_tag_args(x.3)?);
+            Ok(TagOptions {
+                names: [vec![x.0], x.1.names].concat(),
+                ..x.1
+            })
+        }
+    }
+}
+
+// Lists the tags matching any of the glob patterns x, or all of them if there are no patterns
+fn list_tags(x: &[String]) {
+    helpers::get_tags()
+        .iter()
+        .zip(std::iter::repeat(x))
+        .filter(|x| {
+            x.1.is_empty()
+                || x.1
+                    .iter()
+                    .zip(std::iter::repeat(x.0))
+                    .any(|x| helpers::glob_match((x.0.as_bytes(), x.1.as_bytes())))
+        })
+        .for_each(|x| println!("{}", x.0));
+}
+
+fn delete_tags(x: &[String]) {
+    x.iter()
+        .map(|x| (x, helpers::read_raw_ref(&format!("refs/tags/{x}"))))
+        .for_each(|x| match x.1 {
+            Some(_) => {
+                let _ = fs::remove_file(format!(".grit/refs/tags/{}", x.0));
+                remove_empty_ref_directories(("refs/tags", x.0));
+                println!(
+                    "Deleted tag '{}' (was {})",
+                    x.0,
+                    helpers::abbreviate(&x.1.unwrap_or_default())
+                );
+            }
+            None => println!("Tag '{}' not found", x.0),
+        });
+}
+
+// Creates the tag described by the options x, returning what to tell the user. Annotated tags are
+// objects of their own, recording who created them, when and why.
+fn create_tag(x: &TagOptions) -> Result<String, String> {
+    let x: (&TagOptions, &String, Option<&String>) = (x, &x.names[0], x.names.get(1));
+    if !helpers::is_valid_ref_name(x.1) {
+        return Err(format!("'{}' is not a valid tag name", x.1));
+    }
+    if !x.0.force && Path::new(&format!(".grit/refs/tags/{}", x.1)).exists() {
+        return Err(format!("Tag '{}' already exists", x.1));
+    }
+
+    let x: (&TagOptions, &String, String) = (
+        x.0,
+        x.1,
+        match (
+            helpers::resolve_revision(x.2.map_or("HEAD", |x| x.as_str())).map(|x| x.to_string()),
+            x.2,
+        ) {
+            (Ok(x), _) => x,
+            (Err(x), Some(_)) => return Err(x),
+            (Err(_), None) => return Err(String::from("No commits yet, there is nothing to tag")),
+        },
+    );
+
+    let x: (&String, String) = match x.0.annotate {
+        false => (x.1, x.2),
+        true => {
+            let x: (&TagOptions, &String, String, String) =
+                (x.0, x.1, x.2, helpers::get_signature("COMMITTER")?);
+            let x: (&String, String, String, String) = (
+                x.1,
+                x.2,
+                x.3,
+                match combine_messages((x.0.messages.clone(), x.0.files.clone()))? {
+                    Some(x) => clean_message((&x, false)),
+                    None => clean_message((
+                        &edit_message((
+                            "TAG_EDITMSG",
+                            format!(
+                                "\n# Write a message for tag:\n#   {}\n\
+                                 # Lines starting with '#' will be ignored.\n",
+                                x.1
+                            ),
+                        ))?,
+                        true,
+                    )),
+                },
+            );
+            if x.3.is_empty() {
+                return Err(String::from("Aborting tag due to empty message"));
+            }
+
+            (
+                x.0,
+                helpers::write_object((
+                    "tag",
+                    format!(
+                        "object\t{}\ntype\tcommit\ntag\t{}\ntagger\t{}\n\n{}\n",
+                        x.1, x.0, x.2, x.3
+                    )
+                    .as_bytes(),
+                ))
+                .to_string(),
+            )
+        }
+    };
+
+    helpers::write_ref((&format!("refs/tags/{}", x.0), &x.1));
+    Ok(format!("Created tag {}", x.0))
+}
+
+fn tag(x: &[String]) {
+    let x: TagOptions = match parse_tag_args(x) {
+        Ok(x) => x,
+        Err(x) => {
+            println!("{x}");
+            return;
+        }
+    };
+
+    match (x.list, x.delete, x.names.len()) {
+        (true, true, _) => println!("Options -l and -d cannot be used together"),
+        (true, _, _) | (false, false, 0) => list_tags(&x.names),
+        (_, true, 0) => help(),
+        (_, true, _) => delete_tags(&x.names),
+        (_, _, 1 | 2) => match create_tag(&x) {
+            Ok(x) | Err(x) => println!("{x}"),
+        },
+        _ => help(),
+    }
+}
+
+// What merging a single path gives in the format (contents for the working tree or None if the
+// path is deleted, the conflict if the two sides couldn't be combined)
+type MergedPath = (Option<Vec<u8>>, Option<helpers::Conflict>);
+
+// Merges the versions x.1 (merge base, ours, theirs) of path x.0, labeling the conflict markers of
+// their side with x.2. None if our version stays as it is.
+fn merge_path(x: (&String, helpers::Conflict, &str)) -> Option<MergedPath> {
+    match x {
+        // Either both sides made the same change, or only we changed the path
+        x if x.1 .1 == x.1 .2 || x.1 .0 == x.1 .2 => None,
+        // Only they changed the path
+        x if x.1 .0 == x.1 .1 => {
+            Some((x.1 .2.as_ref().and_then(|x| helpers::read_object(x)), None))
+        }
+        // One side deleted the path while the other changed it, the changed version is kept
+        x if x.1 .1.is_none() || x.1 .2.is_none() => Some((
+            x.1 .1
+                .as_ref()
+                .or(x.1 .2.as_ref())
+                .and_then(|x| helpers::read_object(x)),
+            Some(x.1),
+        )),
+        x => {
+            let x: (Vec<u8>, Vec<u8>, Vec<u8>, helpers::Conflict, &str) = (
+                x.1 .0
+                    .as_ref()
+                    .and_then(|x| helpers::read_object(x))
+                    .unwrap_or_default(),
+                x.1 .1
+                    .as_ref()
+                    .and_then(|x| helpers::read_object(x))
+                    .unwrap_or_default(),
+                x.1 .2
+                    .as_ref()
+                    .and_then(|x| helpers::read_object(x))
+                    .unwrap_or_default(),
+                x.1.clone(),
+                x.2,
+            );
+
+            // Binary files can't be merged line by line, our version is kept as it is
+            if [&x.0, &x.1, &x.2].iter().any(|x| x.contains(&0)) {
+                return Some((Some(x.1), Some(x.3)));
+            }
+
+            let x: (String, String, String, helpers::Conflict, &str) = (
+                String::from_utf8_lossy(&x.0).to_string(),
+                String::from_utf8_lossy(&x.1).to_string(),
+                String::from_utf8_lossy(&x.2).to_string(),
+                x.3,
+                x.4,
+            );
+            let x: ((String, bool), helpers::Conflict) = (
+                diff::merge_lines((
+                    &x.0.split_inclusive('\n').collect::<Vec<&str>>(),
+                    &x.1.split_inclusive('\n').collect::<Vec<&str>>(),
+                    &x.2.split_inclusive('\n').collect::<Vec<&str>>(),
+                    "HEAD",
+                    x.4,
+                )),
+                x.3,
+            );
+
+            Some((Some(x.0 .0.into_bytes()), Some(x.1).filter(|_| x.0 .1)))
+        }
+    }
+}
+
+// Writes the contents x.1 to the file x.0 in the working tree, or removes it if x.1 is None
+fn write_working_file(x: (&str, Option<&Vec<u8>>)) {
+    match x.1 {
+        Some(_) => {
+            if let Some(x) = Path::new(x.0).parent() {
+                let _ = fs::create_dir_all(x);
+            }
+            let _ = fs::write(x.0, x.1.unwrap_or(&Vec::new()));
+        }
+        None => {
+            let _ = fs::remove_file(x.0);
+            helpers::remove_empty_parents(x.0);
+        }
+    }
+}
+
+// The message a merge of revision x is committed with
+fn get_merge_message(x: &str) -> String {
+    match x {
+        x if helpers::is_valid_ref_name(x)
+            && Path::new(&format!(".grit/refs/heads/{x}")).is_file() =>
+        {
+            format!("Merge branch '{x}'")
+        }
+        x if helpers::is_valid_ref_name(x)
+            && Path::new(&format!(".grit/refs/tags/{x}")).is_file() =>
+        {
+            format!("Merge tag '{x}'")
+        }
+        x => format!("Merge commit '{x}'"),
+    }
+}
+
+// The result of merging a path in the format (path, our version, what merging gives)
+type MergedEntry = (String, Option<String>, MergedPath);
+
+// Merges every path of the trees x.0 (merge base, ours, theirs), labeling the conflict markers of
+// their side with x.1. Paths where our version stays as it is are left out.
+fn merge_tree_paths(x: (&[helpers::Tree; 3], &str)) -> Vec<MergedEntry> {
+    x.0.iter()
+        .flat_map(|x| x.keys())
+        .cloned()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .zip(std::iter::repeat(x))
+        .filter_map(|x| {
+            Some((
+                x.0.clone(),
+                x.1 .0[1].get(&x.0).cloned(),
+                merge_path((
+                    &x.0,
+                    (
+                        x.1 .0[0].get(&x.0).cloned(),
+                        x.1 .0[1].get(&x.0).cloned(),
+                        x.1 .0[2].get(&x.0).cloned(),
+                    ),
+                    x.1 .1,
+                ))?,
+            ))
+        })
+        .collect()
+}
+
+// The paths the merge results x would write to whose files in the working tree differ from our
+// version, and would lose their local changes
+fn get_overwritten_paths(x: &[MergedEntry]) -> Vec<&String> {
+    x.iter()
+        .filter(|x| get_working_hash(&x.0) != x.1)
+        .map(|x| &x.0)
+        .collect()
+}
+
+// Tells the user about every path x.0 whose versions x.1 couldn't be merged
+fn print_conflicts(x: &[(String, helpers::Conflict)]) {
+    x.iter().for_each(|x| match x.1 {
+        (_, None, _) => println!(
+            "CONFLICT (modify/delete): {} deleted in HEAD and modified in the merged revision",
+            x.0
+        ),
+        (_, _, None) => println!(
+            "CONFLICT (modify/delete): {} deleted in the merged revision and modified in HEAD",
+            x.0
+        ),
+        (None, _, _) => println!("CONFLICT (add/add): Merge conflict in {}", x.0),
+        _ => println!("CONFLICT (content): Merge conflict in {}", x.0),
+    });
+}
+
+// Merges the changes of both sides x.1 (ours) and x.2 (theirs) since their merge base x.3 into the
+// working tree and the index, committing the result unless there are conflicts. x.0 is the
+// revision that was merged.
+fn merge_trees(x: (&String, Option<String>, String, Option<String>)) -> Result<(), String> {
+    if !helpers::get_index().is_empty() || !helpers::get_conflicts().is_empty() {
+        return Err(String::from(
+            "You have staged changes, commit them before merging",
+        ));
+    }
+
+    let x: (&String, String, [helpers::Tree; 3]) = (
+        x.0,
+        x.2.clone(),
+        [x.3, x.1, Some(x.2)]
+            .map(|x| helpers::get_tree(helpers::get_tree_of_commit(x.as_ref()).as_ref())),
+    );
+    let x: (&String, String, Vec<MergedEntry>) = (x.0, x.1, merge_tree_paths((&x.2, x.0)));
+
+    // Everything the merge writes has to be as it is in HEAD, so that no local changes get lost
+    if let Some(x) = Some(get_overwritten_paths(&x.2)).filter(|x| !x.is_empty()) {
+        println!("Your local changes to the following files would be overwritten by the merge:");
+        x.iter().for_each(|x| println!("\t{x}"));
+        return Err(String::from("Commit them, or discard them before merging"));
+    }
+
+    x.2.iter()
+        .for_each(|x| write_working_file((&x.0, x.2 .0.as_ref())));
+    helpers::write_index(
+        &x.2.iter()
+            .filter(|x| x.2 .1.is_none())
+            .map(|x| {
+                (
+                    x.0.clone(),
+                    x.2 .0.as_ref().map(|x| helpers::write_object(("blob", x))),
+                )
+            })
+            .collect(),
+    );
+    helpers::write_conflicts(
+        &x.2.iter()
+            .filter_map(|x| Some((x.0.clone(), x.2 .1.clone()?)))
+            .collect(),
+    );
+    let _ = fs::write(".grit/MERGE_HEAD", format!("{}\n", x.1));
+    let _ = fs::write(".grit/MERGE_MSG", format!("{}\n", get_merge_message(x.0)));
+
+    let x: Vec<(String, helpers::Conflict)> =
+        x.2.into_iter()
+            .filter_map(|x| Some((x.0, x.2 .1?)))
+            .collect();
+    if x.is_empty() {
+        commit(&[String::from("-F"), String::from(".grit/MERGE_MSG")]);
+        if helpers::get_merge_head().is_none() {
+            println!("Merge made by the three-way strategy");
+        }
+        return Ok(());
+    }
+
+    print_conflicts(&x);
+    println!("Automatic merge failed, fix the conflicts and then commit the result");
+    Ok(())
+}
+
+// Merges revision x into HEAD. When HEAD is an ancestor of x this only moves HEAD forward.
+fn merge_revision(x: &String) -> Result<(), String> {
+    if helpers::get_merge_head().is_some() {
+        return Err(String::from(
+            "A merge is in progress, commit to conclude it or run `grit merge --abort`",
+        ));
+    }
+
+    // The revision, the current HEAD, the commit being merged and their merge base
+    let x: (&String, Option<String>, String) = (
+        x,
+        helpers::get_current_head().map(|x| x.to_string()),
+        helpers::resolve_revision(x)?.to_string(),
+    );
+    let x: (&String, Option<String>, String, Option<String>) = (
+        x.0,
+        x.1.clone(),
+        x.2.clone(),
+        x.1.as_ref()
+            .zip(Some(&x.2))
+            .and_then(helpers::get_merge_base),
+    );
+
+    match x {
+        x if x.3.as_ref() == Some(&x.2) => {
+            println!("Already up to date");
+            Ok(())
+        }
+        x if x.3 == x.1 => {
+            if check_out_tree((
+                &helpers::get_head_tree(),
+                &helpers::get_tree(helpers::get_tree_of_commit(Some(&x.2)).as_ref()),
+                false,
+            )) {
+                helpers::update_head((&x.2, &format!("merge {}: Fast-forward", x.0)));
+                println!("Fast-forward to {}", helpers::abbreviate(&x.2));
+            }
+            Ok(())
+        }
+        x => merge_trees(x),
+    }
+}
+
+// Puts every path a merge in progress staged or left conflicted back to how it is in HEAD, and
+// forgets about the merge. Other local changes are kept.
+fn abort_merge() {
+    if helpers::get_merge_head().is_none() {
+        println!("There is no merge to abort");
+        return;
+    }
+
+    let x: (helpers::Tree, BTreeSet<String>) = (
+        helpers::get_head_tree(),
+        helpers::get_index()
+            .into_keys()
+            .chain(helpers::get_conflicts().into_keys())
+            .collect(),
+    );
+    x.1.iter().zip(std::iter::repeat(&x.0)).for_each(|x| {
+        write_working_file((
+            x.0,
+            x.1.get(x.0).and_then(|x| helpers::read_object(x)).as_ref(),
+        ))
+    });
+
+    // Empty the index
+    let _ = File::create(".grit/index").expect("Could not open index file");
+    let _ = fs::remove_file(".grit/MERGE_HEAD");
+    let _ = fs::remove_file(".grit/MERGE_MSG");
+    println!("Merge aborted");
+}
+
+fn merge(x: &[String]) {
+    match x {
+        [x] if x == "--abort" => abort_merge(),
+        [x] if !x.starts_with('-') => {
+            if let Err(x) = merge_revision(x) {
+                println!("{x}");
+            }
+        }
+        _ => help(),
+    }
+}
+
+// The options of grit reset, parsed from its arguments
+#[derive(Default)]
+struct ResetOptions {
+    // --soft, --mixed (the default) or --hard
+    mode: Option<String>,
+    force: bool,
+    target: Option<String>,
+}
+
+fn parse_reset_args(x: &[String]) -> Result<ResetOptions, String> {
+    let x: (Option<&str>, &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(ResetOptions::default()),
+        Some("-f" | "--force") => Ok(ResetOptions {
+            force: true,
+            ..parse_reset_args(x.1)?
+        }),
+        Some("--soft" | "--mixed" | "--hard") => {
+            let x: (String, ResetOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_reset_args(x.1)?);
+            match x.1.mode {
+                Some(_) => Err(String::from(
+                    "Only one of --soft, --mixed and --hard can be given",
+                )),
+                None => Ok(ResetOptions {
+                    mode: Some(x.0),
+                    ..x.1
+                }),
+            }
+        }
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, ResetOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_reset_args(x.1)?);
+            match x.1.target {
+                Some(_) => Err(String::from("Only one revision can be given")),
+                None => Ok(ResetOptions {
+                    target: Some(x.0),
+                    ..x.1
+                }),
+            }
+        }
+    }
+}
+
+// Moves the current branch (or a detached HEAD) to commit x.1. A soft reset keeps both the index
+// and the working tree as they are, so the changes since x.1 end up staged. A mixed reset also
+// empties the index, and a hard reset also makes the working tree match x.1, which throws away
+// uncommitted work and is only done when forced. Returns what to tell the user.
+fn reset_to(x: (&ResetOptions, String)) -> Result<String, String> {
+    let x: (&ResetOptions, String, helpers::Tree) = (
+        x.0,
+        x.1.clone(),
+        helpers::get_tree(helpers::get_tree_of_commit(Some(&x.1)).as_ref()),
+    );
+
+    match x.0.mode.as_deref().unwrap_or("--mixed") {
+        "--soft" => {
+            if helpers::get_merge_head().is_some() {
+                return Err(String::from(
+                    "Cannot do a soft reset in the middle of a merge",
+                ));
+            }
+
+            // The index is relative to HEAD, so it has to be rewritten to keep the tree the next
+            // commit would have
+            let x: (&helpers::Tree, helpers::Tree) = (
+                &x.2,
+                helpers::apply_index((helpers::get_head_tree(), &helpers::get_index())),
+            );
+            helpers::write_index(
+                &helpers::get_differing_paths((x.0, &x.1))
+                    .into_iter()
+                    .zip(std::iter::repeat(&x.1))
+                    .map(|x| (x.0.clone(), x.1.get(&x.0).and_then(|x| x.parse().ok())))
+                    .collect(),
+            );
+        }
+        "--hard" => {
+            let x: (&ResetOptions, &helpers::Tree, Vec<String>) = (
+                x.0,
+                &x.2,
+                helpers::get_changes()
+                    .into_iter()
+                    .filter(|x| x.1 != ('?', '?'))
+                    .map(|x| x.0)
+                    .collect(),
+            );
+            if !x.0.force && !x.2.is_empty() {
+                println!("Your uncommitted changes to the following files would be discarded:");
+                x.2.iter().for_each(|x| println!("\t{x}"));
+                return Err(String::from("Commit them, or use --force to discard them"));
+            }
+
+            if !check_out_tree((&helpers::get_head_tree(), x.1, x.0.force)) {
+                return Err(String::from("Nothing was reset"));
+            }
+            let _ = File::create(".grit/index").expect("Could not open index file");
+        }
+        _ => {
+            // Empty the index
+            let _ = File::create(".grit/index").expect("Could not open index file");
+        }
+    }
+
+    // Any merge in progress is given up on along with the index
+    helpers::update_head((
+        &x.1,
+        &format!(
+            "reset: moving to {}",
+            x.0.target.as_deref().unwrap_or("HEAD")
+        ),
+    ));
+    let _ = fs::remove_file(".grit/MERGE_HEAD");
+    let _ = fs::remove_file(".grit/MERGE_MSG");
+
+    Ok(format!(
+        "HEAD is now at {} {}",
+        helpers::abbreviate(&x.1),
+        get_subject_and_body(&x.1).0
+    ))
+}
+
+fn reset(x: &[String]) {
+    let x: ResetOptions = match parse_reset_args(x) {
+        Ok(x) => x,
+        Err(x) => {
+            println!("{x}");
+            return;
+        }
+    };
+
+    let x: (Result<String, String>, ResetOptions) = (
+        helpers::resolve_revision(x.target.as_deref().unwrap_or("HEAD")).map(|x| x.to_string()),
+        x,
+    );
+    let x: Result<String, String> = match &x.0 {
+        Ok(_) => reset_to((&x.1, x.0.clone().unwrap_or_default())),
+        Err(x) => Err(x.clone()),
+    };
+
+    match x {
+        Ok(x) | Err(x) => println!("{x}"),
+    }
+}
+
+// Undoes the last commit along with all changes since, the same as `grit reset --hard HEAD~1`
+fn fuckgoback(x: &[String]) {
+    reset(
+        &[
+            vec![String::from("--hard"), String::from("HEAD~1")],
+            x.to_vec(),
+        ]
+        .concat(),
+    );
+}
+
+// Lists where reference x (the current branch, or HEAD when detached, by default) has been, newest
+// first, numbered the way "<ref>@{n}" revisions count
+fn reflog(x: &[String]) {
+    let x: Option<String> = match x {
+        [] => Some(String::from("HEAD")),
+        [x] => helpers::get_reflog_ref(x.trim_end_matches("@{0}")),
+        _ => {
+            help();
+            return;
+        }
+    };
+    let x: String = match x {
+        Some(x) => x,
+        None => {
+            println!("Not a branch or tag");
+            return;
+        }
+    };
+
+    for x in helpers::get_reflog(&x)
+        .iter()
+        .rev()
+        .enumerate()
+        .zip(std::iter::repeat(
+            x.trim_start_matches("refs/heads/")
+                .trim_start_matches("refs/tags/")
+                .trim_start_matches("refs/"),
+        ))
+    {
+        println!(
+            "{} {}@{{{}}}: {}",
+            helpers::abbreviate(&x.0 .1 .1),
+            x.1,
+            x.0 .0,
+            x.0 .1 .3
+        );
+    }
+}
+
+// The options of grit stash, parsed from the arguments after its subcommand
+#[derive(Default)]
+struct StashOptions {
+    message: Option<String>,
+    include_untracked: bool,
+    patch: bool,
+    // The stash entry to use, "stash@{n}" or just n
+    entry: Option<String>,
+}
+
+fn parse_stash_args(x: &[String]) -> Result<StashOptions, String> {
+    let x: (Option<&str>, Option<&String>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1),
+        x.get(2..).unwrap_or_default(),
+        x.get(1..).unwrap_or_default(),
+    );
+
+    match x.0 {
+        None => Ok(StashOptions::default()),
+        Some("-m" | "--message") => Ok(StashOptions {
+            message: Some(x.1.cloned().ok_or("Option -m requires a message")?),
+            ..parse_stash_args(x.2)?
+        }),
+        Some("-u" | "--include-untracked") => Ok(StashOptions {
+            include_untracked: true,
+            ..parse_stash_args(x.3)?
+        }),
+        Some("-p" | "--patch") => Ok(StashOptions {
+            patch: true,
+            ..parse_stash_args(x.3)?
+        }),
+        Some(x) if x.starts_with('-') => Err(format!("Unknown option {x}")),
+        Some(_) => {
+            let x: (String, StashOptions) =
+                (x.0.unwrap_or_default().to_string(), parse_stash_args(x.3)?);
+            match x.1.entry {
+                Some(_) => Err(String::from("Only one stash entry can be given")),
+                None => Ok(StashOptions {
+                    entry: Some(x.0),
+                    ..x.1
+                }),
+            }
+        }
+    }
+}
+
+// A stash entry in the format (position counted from the newest, commit)
+type StashEntry = (usize, String);
+
+// Stash entry x, the latest one by default
+fn get_stash_entry(x: Option<&String>) -> Result<StashEntry, String> {
+    let x: (Option<&String>, Vec<helpers::ReflogEntry>) = (x, helpers::get_reflog("refs/stash"));
+    if x.1.is_empty() {
+        return Err(String::from("No stash entries found"));
+    }
+
+    let x: (String, Vec<helpers::ReflogEntry>) = (x.0.map_or("0", |x| x.as_str()).to_string(), x.1);
+    let x: (String, Option<usize>, Vec<helpers::ReflogEntry>) = (
+        x.0.clone(),
+        x.0.strip_prefix("stash@{")
+            .and_then(|x| x.strip_suffix('}'))
+            .unwrap_or(&x.0)
+            .parse::<usize>()
+            .ok(),
+        x.1,
+    );
+
+    x.1.zip(Some(&x.2))
+        .and_then(|x| Some((x.0, x.1.iter().rev().nth(x.0)?.1.clone())))
+        .ok_or(format!("{} is not a valid stash entry", x.0))
+}
+
+// Writes a commit of tree x.0 with the parents x.1 and the message x.2 for a stash entry
+fn write_stash_commit(x: (String, Vec<String>, String, &helpers::Signatures)) -> String {
+    helpers::write_object((
+        "commit",
+        helpers::Commit {
+            tree: x.0,
+            parents: x.1,
+            headers: vec![
+                (String::from("author"), x.3 .0.clone()),
+                (String::from("committer"), x.3 .1.clone()),
+            ],
+            message: x.2,
+        }
+        .to_string()
+        .as_bytes(),
+    ))
+    .to_string()
+}
+
+// What a stash entry is labeled with, in the format (branch HEAD is on, "<short id> <subject>" of
+// its commit)
+type StashLabel = (String, String);
+
+// The label of stash entries made on top of commit x
+fn get_stash_label(x: &String) -> StashLabel {
+    (
+        helpers::get_head_ref().map_or(String::from("(no branch)"), |x| {
+            x.trim_start_matches("refs/heads/").to_string()
+        }),
+        format!("{} {}", helpers::abbreviate(x), get_subject_and_body(x).0),
+    )
+}
+
+// The working tree version of every path that is tracked or was in HEAD, None for files that were
+// deleted (or whose removal is staged). The contents are stored as blobs.
+fn get_working_tree_changes() -> Vec<helpers::TreeChange> {
+    let x: helpers::Tree = helpers::apply_index((helpers::get_head_tree(), &helpers::get_index()));
+
+    x.keys()
+        .cloned()
+        .chain(helpers::get_head_tree().into_keys())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .zip(std::iter::repeat(&x))
+        .map(|x| match x.1.contains_key(&x.0) {
+            true => (
+                x.0.clone(),
+                fs::read(&x.0)
+                    .ok()
+                    .map(|x| helpers::write_object(("blob", &x)).to_string()),
+            ),
+            false => (x.0, None),
+        })
+        .collect()
+}
+
+// Saves the staged and unstaged changes (and the untracked files if asked to) as a stash entry,
+// then puts the working tree and the index back to how they are in HEAD. The entry is a commit of
+// the working tree whose parents are HEAD, a commit of the index and, if there are any, a commit
+// of the untracked files.
+fn push_stash(x: &StashOptions) -> Result<String, String> {
+    let x: (&StashOptions, String) = (
+        x,
+        helpers::get_current_head()
+            .ok_or("You do not have the initial commit yet")?
+            .to_string(),
+    );
+    if !helpers::get_conflicts().is_empty() {
+        return Err(String::from(
+            "You have unmerged files, resolve them before stashing",
+        ));
+    }
+    let x: (&StashOptions, String, helpers::Signatures) = (
+        x.0,
+        x.1,
+        helpers::get_signature("AUTHOR")
+            .and_then(|x| Ok((x, helpers::get_signature("COMMITTER")?)))?,
+    );
+
+    // What gets saved: the working tree and the untracked files
+    let x: (
+        &StashOptions,
+        String,
+        helpers::Signatures,
+        Vec<helpers::TreeChange>,
+        Vec<String>,
+    ) = (
+        x.0,
+        x.1,
+        x.2,
+        get_working_tree_changes(),
+        helpers::get_changes()
+            .into_iter()
+            .filter(|_| x.0.include_untracked)
+            .filter(|x| x.1 == ('?', '?'))
+            .map(|x| x.0)
+            .collect(),
+    );
+    if helpers::get_index().is_empty()
+        && x.4.is_empty()
+        && x.3
+            .iter()
+            .zip(std::iter::repeat(&helpers::get_head_tree()))
+            .all(|x| x.1.get(&x.0 .0) == x.0 .1.as_ref())
+    {
+        return Err(String::from("No local changes to save"));
+    }
+
+    let x: (
+        &StashOptions,
+        String,
+        helpers::Signatures,
+        Vec<helpers::TreeChange>,
+        Vec<String>,
+        StashLabel,
+    ) = (x.0, x.1.clone(), x.2, x.3, x.4, get_stash_label(&x.1));
+
+    // The parents of the entry: HEAD, the commit of the index and the commit of the untracked files
+    let x: (
+        &StashOptions,
+        String,
+        helpers::Signatures,
+        Vec<helpers::TreeChange>,
+        Vec<String>,
+        StashLabel,
+        Vec<String>,
+    ) = (
+        x.0,
+        x.1.clone(),
+        x.2.clone(),
+        x.3,
+        x.4.clone(),
+        x.5.clone(),
+        std::iter::once(x.1.clone())
+            .chain(std::iter::once(write_stash_commit((
+                helpers::apply_tree_changes((
+                    helpers::get_tree_of_commit(Some(&x.1)).as_ref(),
+                    helpers::get_index()
+                        .into_iter()
+                        .map(|x| (x.0, x.1.map(|x| x.to_string())))
+                        .collect(),
+                )),
+                vec![x.1.clone()],
+                format!("index on {}: {}", x.5 .0, x.5 .1),
+                &x.2,
+            ))))
+            .chain((!x.4.is_empty()).then(|| {
+                write_stash_commit((
+                    helpers::apply_tree_changes((
+                        None,
+                        x.4.iter()
+                            .map(|x| {
+                                (
+                                    x.clone(),
+                                    fs::read(x)
+                                        .ok()
+                                        .map(|x| helpers::write_object(("blob", &x)).to_string()),
+                                )
+                            })
+                            .collect(),
+                    )),
+                    Vec::new(),
+                    format!("untracked files on {}: {}", x.5 .0, x.5 .1),
+                    &x.2,
+                ))
+            }))
+            .collect(),
+    );
+
+    let x: (Vec<helpers::TreeChange>, Vec<String>, String, String) = (
+        x.3.clone(),
+        x.4.clone(),
+        write_stash_commit((
+            helpers::apply_tree_changes((helpers::get_tree_of_commit(Some(&x.1)).as_ref(), x.3)),
+            x.6,
+            get_stash_message((x.0, &x.5)),
+            &x.2,
+        )),
+        get_stash_message((x.0, &x.5)),
+    );
+    helpers::update_ref(("refs/stash", &x.2, &x.3));
+
+    // Everything that was saved goes back to how it is in HEAD
+    x.0.iter()
+        .zip(std::iter::repeat(&helpers::get_head_tree()))
+        .filter(|x| x.1.get(&x.0 .0) != x.0 .1.as_ref())
+        .for_each(|x| {
+            write_working_file((
+                &x.0 .0,
+                x.1.get(&x.0 .0)
+                    .and_then(|x| helpers::read_object(x))
+                    .as_ref(),
+            ))
+        });
+    x.1.iter().for_each(|x| write_working_file((x, None)));
+    let _ = File::create(".grit/index").expect("Could not open index file");
+
+    Ok(format!("Saved working directory and index state {}", x.3))
+}
+
+// The message of a stash entry with the options x.0 on HEAD labeled x.1 (see get_stash_label)
+fn get_stash_message(x: (&StashOptions, &StashLabel)) -> String {
+    match &x.0.message {
+        Some(_) => format!(
+            "On {}: {}",
+            x.1 .0,
+            x.0.message.as_deref().unwrap_or_default()
+        ),
+        None => format!("WIP on {}: {}", x.1 .0, x.1 .1),
+    }
+}
+
+// Lists the stash entries, newest first
+fn list_stashes() {
+    for x in helpers::get_reflog("refs/stash").iter().rev().enumerate() {
+        println!("stash@{{{}}}: {}", x.0, x.1 .3);
+    }
+}
+
+// Shows the paths a stash entry changes compared to the commit it was made on, or the full diff
+// with -p
+fn show_stash(x: &StashOptions) -> Result<(), String> {
+    let x: (bool, helpers::Commit) = (
+        x.patch,
+        helpers::read_commit(Some(&get_stash_entry(x.entry.as_ref())?.1))
+            .ok_or("The stash entry is not a commit")?,
+    );
+    let x: (bool, (helpers::Tree, helpers::Tree, bool)) = (
+        x.0,
+        (
+            helpers::get_tree(helpers::get_tree_of_commit(x.1.parents.first()).as_ref()),
+            helpers::get_tree(Some(&x.1.tree)),
+            false,
+        ),
+    );
+
+    if x.0 {
+        print_diff((&x.1, &DiffOptions::default()));
+        return Ok(());
+    }
+    helpers::get_differing_paths((&x.1 .0, &x.1 .1))
+        .iter()
+        .zip(std::iter::repeat(&x.1))
+        .for_each(|x| {
+            println!(
+                "{:<12}{}",
+                describe_change(match (x.1 .0.contains_key(x.0), x.1 .1.contains_key(x.0)) {
+                    (false, _) => 'A',
+                    (_, false) => 'D',
+                    _ => 'M',
+                }),
+                x.0
+            )
+        });
+    Ok(())
+}
+
+// Applies the changes of a stash entry to the working tree by merging them with the current
+// state, and restores the untracked files it saved. Files the entry added are staged so that
+// they're tracked, the other changes are left unstaged. Returns the entry and whether it applied
+// without conflicts.
+fn apply_stash(x: &StashOptions) -> Result<(StashEntry, bool), String> {
+    let x: StashEntry = get_stash_entry(x.entry.as_ref())?;
+    if !helpers::get_conflicts().is_empty() {
+        return Err(String::from(
+            "You have unmerged files, resolve them before applying a stash",
+        ));
+    }
+    let x: (StashEntry, helpers::Commit) = (
+        x.clone(),
+        helpers::read_commit(Some(&x.1)).ok_or("The stash entry is not a commit")?,
+    );
+
+    // The untracked files are put back as they were, so they can't replace anything
+    let x: (StashEntry, helpers::Commit, helpers::Tree) = (
+        x.0,
+        x.1.clone(),
+        helpers::get_tree(helpers::get_tree_of_commit(x.1.parents.get(2)).as_ref()),
+    );
+    if let Some(x) = x.2.keys().find(|x| Path::new(x).exists()) {
+        return Err(format!(
+            "{x} already exists, move it away before applying the stash"
+        ));
+    }
+
+    // The changes of the entry since the commit it was made on are merged with HEAD and the index
+    let x: (StashEntry, helpers::Tree, Vec<MergedEntry>) = (
+        x.0.clone(),
+        x.2,
+        merge_tree_paths((
+            &[
+                helpers::get_tree(helpers::get_tree_of_commit(x.1.parents.first()).as_ref()),
+                helpers::apply_index((helpers::get_head_tree(), &helpers::get_index())),
+                helpers::get_tree(Some(&x.1.tree)),
+            ],
+            &format!("stash@{{{}}}", x.0 .0),
+        )),
+    );
+    if let Some(x) = Some(get_overwritten_paths(&x.2)).filter(|x| !x.is_empty()) {
+        println!("Your local changes to the following files would be overwritten by the stash:");
+        x.iter().for_each(|x| println!("\t{x}"));
+        return Err(String::from(
+            "Commit them, or discard them before applying the stash",
+        ));
+    }
+
+    x.2.iter()
+        .for_each(|x| write_working_file((&x.0, x.2 .0.as_ref())));
+    x.1.iter().for_each(|x| {
+        write_working_file((x.0, helpers::read_object(x.1).as_ref()));
+    });
+
+    let x: (
+        StashEntry,
+        Vec<MergedEntry>,
+        Vec<(String, helpers::Conflict)>,
+    ) = (
+        x.0,
+        x.2.clone(),
+        x.2.into_iter()
+            .filter_map(|x| Some((x.0, x.2 .1?)))
+            .collect(),
+    );
+    helpers::write_index(
+        &helpers::get_index()
+            .into_iter()
+            .chain(
+                x.1.iter()
+                    .filter(|x| x.1.is_none() && x.2 .1.is_none())
+                    .filter_map(|x| {
+                        Some((
+                            x.0.clone(),
+                            Some(helpers::write_object(("blob", x.2 .0.as_ref()?))),
+                        ))
+                    }),
+            )
+            .zip(std::iter::repeat(&x.2))
+            .filter(|x| {
+                !x.1.iter()
+                    .zip(std::iter::repeat(&x.0 .0))
+                    .any(|x| x.0 .0 == *x.1)
+            })
+            .map(|x| x.0)
+            .collect(),
+    );
+    helpers::write_conflicts(&x.2.iter().cloned().collect());
+    print_conflicts(&x.2);
+
+    Ok((x.0, x.2.is_empty()))
+}
+
+// Removes stash entry x.0 (whose commit is x.1), counted from the newest, from the stash reflog.
+// refs/stash points at whatever entry is the newest afterwards.
+fn drop_stash(x: StashEntry) -> String {
+    let x: (usize, String, Vec<String>) = (
+        x.0,
+        x.1,
+        fs::read_to_string(".grit/logs/refs/stash")
+            .unwrap_or_default()
+            .lines()
+            .rev()
+            .enumerate()
+            .zip(std::iter::repeat(x.0))
+            .filter(|x| x.0 .0 != x.1)
+            .map(|x| x.0 .1.to_string())
+            .collect::<Vec<String>>()
+            .into_iter()
+            .rev()
+            .collect(),
+    );
+
+    match x.2.is_empty() {
+        true => {
+            let _ = fs::remove_file(".grit/refs/stash");
+            let _ = fs::remove_file(".grit/logs/refs/stash");
+        }
+        false => {
+            let _ = fs::write(".grit/logs/refs/stash", format!("{}\n", x.2.join("\n")));
+            if let Some(x) = helpers::get_reflog("refs/stash").last() {
+                helpers::write_ref(("refs/stash", &x.1));
+            }
+        }
+    }
+
+    format!("Dropped stash@{{{}}} ({})", x.0, x.1)
+}
+
+fn stash(x: &[String]) {
+    // Without a subcommand the changes are pushed, e.g. `grit stash -m <message>`
+    let x: (Option<&str>, &[String], &[String]) = (
+        x.first().map(String::as_str),
+        x.get(1..).unwrap_or_default(),
+        x,
+    );
+    let x: (&str, Result<StashOptions, String>) = match x.0 {
+        Some("push" | "list" | "show" | "apply" | "pop" | "drop") => {
+            (x.0.unwrap_or_default(), parse_stash_args(x.1))
+        }
+        Some(x) if !x.starts_with('-') => ("", Err(format!("Unknown subcommand {x}"))),
+        _ => ("push", parse_stash_args(x.2)),
+    };
+
+    match x {
+        (_, Err(x)) => println!("{x}"),
+        ("push", Ok(x)) => match push_stash(&x) {
+            Ok(x) | Err(x) => println!("{x}"),
+        },
+        ("list", Ok(_)) => list_stashes(),
+        ("show", Ok(x)) => {
+            if let Err(x) = show_stash(&x) {
+                println!("{x}");
+            }
+        }
+        ("apply", Ok(x)) => match apply_stash(&x) {
+            Ok(x) if x.1 => println!("Applied stash@{{{}}}", x.0 .0),
+            Ok(_) => println!("Applying the stash caused conflicts, fix them and add the files"),
+            Err(x) => println!("{x}"),
+        },
+        ("pop", Ok(x)) => match apply_stash(&x) {
+            Ok(x) if x.1 => println!("{}", drop_stash(x.0)),
+            Ok(_) => println!("The stash entry is kept in case you need it again"),
+            Err(x) => println!("{x}"),
+        },
+        ("drop", Ok(x)) => match get_stash_entry(x.entry.as_ref()) {
+            Ok(x) => println!("{}", drop_stash(x)),
+            Err(x) => println!("{x}"),
+        },
+        _ => help(),
+    }
+}
+
+// Runs the command x.2 that was called as `grit <x.0> <x.1>...`, recording what it changes in the
+// operation log
+fn record<F>(x: (&str, &[String], F))
+where
+    F: FnOnce(),
+{
+    oplog::record((
+        &std::iter::once(x.0)
+            .chain(x.1.iter().map(String::as_str))
+            .collect::<Vec<&str>>()
+            .join(" "),
+        x.2,
+    ));
+}
+
+// Only refs and the index are restored, the working tree is left as it is
+fn print_restored(x: (&str, &oplog::Operation)) {
+    println!("{} {}: {}", x.0, helpers::abbreviate(&x.1 .0), x.1 .4);
+    println!("The working tree was not changed, see `grit status` for how it differs");
+}
+
+fn undo() {
+    match oplog::undo() {
+        Ok(Some(x)) => print_restored(("Undid operation", &x)),
+        Ok(None) => println!("Nothing to undo"),
+        Err(x) => println!("Could not undo: {x}"),
+    }
+}
+
+fn list_operations() {
+    for x in oplog::get_operations().iter().rev() {
+        println!(
+            "{} {}: {}",
+            helpers::abbreviate(&x.0),
+            helpers::parse_signature(&x.3)
+                .and_then(|x| helpers::format_date(&x.2))
+                .unwrap_or_default(),
+            x.4
+        );
+    }
+}
+
+fn op(x: &[String]) {
+    match (
+        x.first().map(String::as_str),
+        x.get(1..).unwrap_or_default(),
+    ) {
+        (Some("log"), []) => list_operations(),
+        (Some("restore"), [x]) => match oplog::find_operation(x) {
+            Ok(x) => match oplog::restore_operation(&x) {
+                Ok(()) => print_restored(("Restored the repository to operation", &x)),
+                Err(x) => println!("Could not restore: {x}"),
+            },
+            Err(x) => println!("{x}"),
+        },
+        _ => help(),
+    }
+}
+
+fn check_ignore(x: &[String]) {
+    if x.is_empty() {
+        return help();
+    }
+
+    // Print the rule that decides whether each path is ignored, in the same format as
+    // `git check-ignore -v`. Paths that no rule matches are left out.
+    x.iter()
+        .map(|x| helpers::normalize_path(x))
+        .filter_map(|x| Some((ignore::get_deciding_rule((&x, Path::new(&x).is_dir()))?, x)))
+        .for_each(|x| println!("{}:{}:{}\t{}", x.0.source, x.0.line, x.0.pattern, x.1));
+}
+
+fn config(x: &[String]) {
+    match x {
+        [x] => match config::get(x) {
+            Some(x) => println!("{x}"),
+            None => println!("{x} is not set"),
+        },
+        // Keys are written as "key = value" lines, so they can't contain whitespace or `=`
+        [x, _] if !x.contains('.') || x.contains(char::is_whitespace) || x.contains('=') => {
+            println!("Invalid key '{x}'")
+        }
+        [_, x] if x.contains('\n') => println!("Values can't span multiple lines"),
+        [_, _] => config::set((&x[0], &x[1])),
+        _ => help(),
+    }
+}
+
+// Moves the objects of a repository from .grit itself into .grit/objects
+fn migrate_objects() {
+    println!(
+        "Moved {} objects into .grit/objects",
+        helpers::move_legacy_objects()
+    );
+
+    let x: usize = helpers::get_legacy_object_ids().len();
+    if x > 0 {
+        println!("{x} objects are in the legacy format, run `grit migrate-ids` to convert them");
+    }
+}
+
+fn migrate_ids() {
+    let x: helpers::IdMapping = RefCell::new(HashMap::new());
+
+    // Everything reachable from a branch, a tag (or a detached HEAD) is rewritten with knowledge of
+    // what kind of object it is
+    helpers::get_branches()
+        .iter()
+        .map(|x| format!("refs/heads/{x}"))
+        .chain(helpers::get_tags().iter().map(|x| format!("refs/tags/{x}")))
+        .chain(
+            helpers::get_head_ref()
+                .is_none()
+                .then(|| String::from("HEAD")),
+        )
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| {
+            if let Some(x) = helpers::read_raw_ref(&x.0).zip(Some(x)).and_then(|x| {
+                Some((
+                    x.1 .0,
+                    helpers::migrate_object((
+                        helpers::get_commit_header((Some(&x.0), "tag")).map_or("commit", |_| "tag"),
+                        &x.0,
+                        x.1 .1,
+                    ))?,
+                ))
+            }) {
+                helpers::write_ref((&x.0, &x.1.to_string()));
+            }
+        });
+
+    // Commits that only a reflog or a merge in progress still point at are commits just the same
+    helpers::list_refs("logs/refs")
+        .iter()
+        .map(|x| format!("refs/{x}"))
+        .chain(std::iter::once(String::from("HEAD")))
+        .flat_map(|x| helpers::get_reflog(&x))
+        .flat_map(|x| [x.0, x.1])
+        .chain(helpers::get_merge_head())
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| {
+            let _ = helpers::migrate_object(("commit", &x.0, x.1));
+        });
+
+    // Unreachable legacy objects (e.g. files that were added but not committed yet) can only be
+    // treated as blobs
+    helpers::get_object_ids()
+        .iter()
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| {
+            let _ = helpers::migrate_object(("blob", x.0, x.1));
+        });
+
+    // The index, a merge in progress and the reflogs refer to objects by id as well
+    helpers::write_index(
+        &helpers::get_raw_index()
+            .into_iter()
+            .zip(std::iter::repeat(&x))
+            .filter_map(|x| {
+                Some((
+                    x.0 .0,
+                    x.0 .1
+                        .zip(Some(x.1))
+                        .map(|x| helpers::get_migrated_id((&x.0, x.1)).parse())
+                        .transpose()
+                        .ok()?,
+                ))
+            })
+            .collect(),
+    );
+    helpers::write_conflicts(
+        &helpers::get_conflicts()
+            .into_iter()
+            .zip(std::iter::repeat(&x))
+            .map(|x| {
+                (
+                    x.0 .0,
+                    (
+                        x.0 .1
+                             .0
+                            .zip(Some(x.1))
+                            .map(|x| helpers::get_migrated_id((&x.0, x.1))),
+                        x.0 .1
+                             .1
+                            .zip(Some(x.1))
+                            .map(|x| helpers::get_migrated_id((&x.0, x.1))),
+                        x.0 .1
+                             .2
+                            .zip(Some(x.1))
+                            .map(|x| helpers::get_migrated_id((&x.0, x.1))),
+                    ),
+                )
+            })
+            .collect(),
+    );
+    if let Some(x) = helpers::get_merge_head().zip(Some(&x)) {
+        let _ = fs::write(
+            ".grit/MERGE_HEAD",
+            format!("{}\n", helpers::get_migrated_id((&x.0, x.1))),
+        );
+    }
+    helpers::list_refs("logs/refs")
+        .iter()
+        .map(|x| format!("refs/{x}"))
+        .chain(std::iter::once(String::from("HEAD")))
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| helpers::migrate_reflog((&x.0, x.1)));
+
+    // Remove everything that was stored under an id that is no longer in use. Objects that couldn't
+    // be read weren't rewritten, so they're left alone.
+    let x: (HashSet<String>, Vec<String>) = (
+        x.borrow().values().map(|x| x.to_string()).collect(),
+        x.borrow()
+            .iter()
+            .filter(|x| *x.0 != x.1.to_string())
+            .map(|x| x.0.clone())
+            .collect(),
+    );
+
+    x.1.iter()
+        .zip(std::iter::repeat(&x.0))
+        .filter(|x| !x.1.contains(x.0))
+        .for_each(|x| {
+            helpers::remove_object(x.0);
+        });
+
+    println!("Rewrote {} object ids", x.1.len());
+
+    // The snapshots in the operation log still refer to the old ids, which are gone now
+    if !x.1.is_empty()
+        && fs::metadata(".grit/oplog").is_ok_and(|x| x.len() > 0)
+        && fs::remove_file(".grit/oplog").is_ok()
+    {
+        println!(
+            "Cleared the operation log, since its snapshots refer to objects by their old ids"
+        );
+    }
+}
+
+fn main() {
+    #[allow(unused_variables)]
+    let x: i32 = 42;
+
+    let x: Vec<String> = env::args().collect();
+    let x: (Option<&str>, &[String]) =
+        (x.get(1).map(String::as_str), x.get(2..).unwrap_or_default());
+
+    match x {
+        (Some("init"), []) => initialize(),
+        (Some("status"), x) => check_initialized(|| status(x)),
+        (Some("migrate-objects"), []) => check_initialized(migrate_objects),
+        // Not recorded, since it removes the objects that earlier snapshots are stored under
+        (Some("migrate-ids"), []) => check_initialized(migrate_ids),
+        (Some("add"), x) if !x.is_empty() => check_initialized(|| record(("add", x, || add(x)))),
+        (Some("check-ignore"), x) => check_initialized(|| check_ignore(x)),
+        (Some("config"), x) => check_initialized(|| config(x)),
+        (Some("commit"), x) => check_initialized(|| record(("commit", x, || commit(x)))),
+        (Some("log"), x) => check_initialized(|| log(x)),
+        (Some("diff"), x) => check_initialized(|| diff(x)),
+        (Some("show"), x) => check_initialized(|| show(x)),
+        (Some("branch"), x) => check_initialized(|| record(("branch", x, || branch(x)))),
+        (Some("switch"), x) => check_initialized(|| record(("switch", x, || switch(x)))),
+        (Some("checkout"), x) => check_initialized(|| record(("checkout", x, || checkout(x)))),
+        (Some("tag"), x) => check_initialized(|| record(("tag", x, || tag(x)))),
+        (Some("merge"), x) => check_initialized(|| record(("merge", x, || merge(x)))),
+        (Some("rm"), x) => check_initialized(|| record(("rm", x, || rm(x)))),
+        (Some("mv"), x) => check_initialized(|| record(("mv", x, || mv(x)))),
+        (Some("reset"), x) => check_initialized(|| record(("reset", x, || reset(x)))),
+        (Some("fuckgoback"), x) => {
+            check_initialized(|| record(("fuckgoback", x, || fuckgoback(x))))
         }
-        _ => check_initialized(|| help()),
+        (Some("stash"), x) => check_initialized(|| record(("stash", x, || stash(x)))),
+        (Some("reflog"), x) => check_initialized(|| reflog(x)),
+        (Some("undo"), []) => check_initialized(undo),
+        (Some("op"), x) => check_initialized(|| op(x)),
+        _ => check_initialized(help),
     };
 }
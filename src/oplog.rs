@@ -0,0 +1,285 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::helpers;
+
+// The metadata files besides the references below refs/ that make up the state of a repository:
+// where HEAD points, the index, a merge waiting for its conflicts to be resolved and the stash
+// entries, which are kept in the reflog of refs/stash
+const STATE_FILES: [&str; 5] = [
+    "HEAD",
+    "index",
+    "MERGE_HEAD",
+    "MERGE_MSG",
+    "logs/refs/stash",
+];
+
+// The state of a repository in the format (path relative to .grit -> id of the object holding the
+// contents of the file). The working tree isn't part of it.
+pub type Snapshot = BTreeMap<String, String>;
+
+// The contents of the metadata files of a snapshot, by path relative to .grit
+type SnapshotFiles = BTreeMap<String, Vec<u8>>;
+
+// An entry of the operation log in the format (id, snapshot before, snapshot after, identity,
+// description)
+pub type Operation = (String, String, String, String, String);
+
+// Stores the contents of every metadata file as an object. Empty files mean the same as missing
+// ones (e.g. an index with nothing staged), so they're left out.
+pub fn take_snapshot() -> Snapshot {
+    STATE_FILES
+        .iter()
+        .map(|x| x.to_string())
+        .chain(
+            helpers::list_refs("refs")
+                .into_iter()
+                .map(|x| format!("refs/{x}")),
+        )
+        .filter_map(|x| {
+            Some(x.clone()).zip(
+                fs::read(format!(".grit/{x}"))
+                    .ok()
+                    .filter(|x| !x.is_empty()),
+            )
+        })
+        .map(|x| (x.0, helpers::write_object(("blob", &x.1)).to_string()))
+        .collect()
+}
+
+// Snapshots are stored like trees, one "blob\t<hash>\t<path>" line per file
+fn write_snapshot(x: &Snapshot) -> String {
+    helpers::write_object((
+        "tree",
+        x.iter()
+            .map(|x| format!("blob\t{}\t{}", x.1, x.0))
+            .collect::<Vec<String>>()
+            .join("\n")
+            .as_bytes(),
+    ))
+    .to_string()
+}
+
+// The snapshot stored in object x
+fn read_snapshot(x: &str) -> Result<Snapshot, String> {
+    Ok(String::from_utf8_lossy(
+        &helpers::read_object(x)
+            .ok_or_else(|| format!("Snapshot {} is missing", helpers::abbreviate(x)))?,
+    )
+    .lines()
+    .filter_map(|x| x.strip_prefix("blob\t"))
+    .filter_map(|x| x.split_once('\t'))
+    .map(|x| (x.1.to_string(), x.0.to_string()))
+    .collect())
+}
+
+// The contents of every metadata file in the snapshot stored in object x, by path relative to
+// .grit. Everything is read before anything gets restored, so that a snapshot whose objects are
+// gone can't leave the repository half restored.
+fn read_snapshot_files(x: &str) -> Result<SnapshotFiles, String> {
+    read_snapshot(x)?
+        .into_iter()
+        .map(|x| {
+            Some(x.0.clone())
+                .zip(helpers::read_object(&x.1))
+                .ok_or_else(|| {
+                    format!(
+                        "Object {} holding .grit/{} is missing",
+                        helpers::abbreviate(&x.1),
+                        x.0
+                    )
+                })
+        })
+        .collect()
+}
+
+// Every operation, oldest first. Each line of .grit/oplog is
+// "<id> <snapshot before> <snapshot after> <identity>\t<description>".
+pub fn get_operations() -> Vec<Operation> {
+    fs::read_to_string(".grit/oplog")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| {
+            let x: (&str, &str) = x.split_once('\t').unwrap_or((x, ""));
+            let x: (Vec<&str>, &str) = (x.0.splitn(4, ' ').collect(), x.1);
+
+            Some((
+                x.0.first()?.to_string(),
+                x.0.get(1)?.to_string(),
+                x.0.get(2)?.to_string(),
+                x.0.get(3).unwrap_or(&"").to_string(),
+                x.1.to_string(),
+            ))
+        })
+        .collect()
+}
+
+// Appends the operation x.2 that took the repository from snapshot x.0 to x.1 to the log. Its id
+// is the hash of everything else on its line, which includes the time it happened.
+fn append_operation(x: (&Snapshot, &Snapshot, &str)) {
+    let x: String = format!(
+        "{} {} {}\t{}",
+        write_snapshot(x.0),
+        write_snapshot(x.1),
+        helpers::get_log_identity(),
+        x.2.replace('\n', " ")
+    );
+    let x: String = format!("{} {}\n", helpers::hash_bytes(x.as_bytes()), x);
+
+    if let Some(x) = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(".grit/oplog")
+        .ok()
+        .zip(Some(x))
+    {
+        let _ = (&x.0).write_all(x.1.as_bytes());
+    }
+}
+
+// Runs x.1 and records it as the operation described by x.0, if it changed anything
+pub fn record<F>(x: (&str, F))
+where
+    F: FnOnce(),
+{
+    let x: (&str, F, Snapshot) = (x.0, x.1, take_snapshot());
+    (x.1)();
+
+    let x: (&str, Snapshot, Snapshot) = (x.0, x.2, take_snapshot());
+    if x.1 != x.2 {
+        append_operation((&x.1, &x.2, x.0));
+    }
+}
+
+// The commit every reference (and HEAD) currently points at
+fn get_ref_commits() -> HashMap<String, String> {
+    helpers::list_refs("refs")
+        .into_iter()
+        .map(|x| format!("refs/{x}"))
+        .chain(std::iter::once(String::from("HEAD")))
+        .filter_map(|x| Some(x.clone()).zip(helpers::read_raw_ref(&x)))
+        .collect()
+}
+
+// Removes the directories below .grit/refs that were left empty after deleting the file x
+fn remove_empty_ref_directories(x: &Path) {
+    if let Some(x) = x.parent().filter(|x| x.starts_with(".grit/refs/")) {
+        if fs::remove_dir(x).is_ok() {
+            remove_empty_ref_directories(x);
+        }
+    }
+}
+
+// Puts every metadata file back the way it was in snapshot x.0. References that end up pointing
+// somewhere else get a reflog entry with the reason x.1, unless their reflog was restored too.
+fn restore_snapshot(x: (&SnapshotFiles, &str)) {
+    let x: (&SnapshotFiles, &str, HashMap<String, String>) = (x.0, x.1, get_ref_commits());
+
+    take_snapshot()
+        .into_keys()
+        .chain(x.0.keys().cloned())
+        .collect::<BTreeSet<String>>()
+        .iter()
+        .zip(std::iter::repeat(x.0))
+        .for_each(|x| {
+            let x: (String, Option<Vec<u8>>) = (format!(".grit/{}", x.0), x.1.get(x.0).cloned());
+
+            match x.1 {
+                Some(_) => {
+                    if let Some(x) = Path::new(&x.0).parent() {
+                        let _ = fs::create_dir_all(x);
+                    }
+                    let _ = fs::write(&x.0, x.1.unwrap_or_default());
+                }
+                None => {
+                    let _ = fs::remove_file(&x.0);
+                    remove_empty_ref_directories(Path::new(&x.0));
+                }
+            }
+        });
+
+    get_ref_commits()
+        .iter()
+        .zip(std::iter::repeat((&x.2, x.1)))
+        .filter(|x| x.1 .0.get(x.0 .0) != Some(x.0 .1))
+        .filter(|x| !STATE_FILES.contains(&format!("logs/{}", x.0 .0).as_str()))
+        .for_each(|x| {
+            helpers::append_reflog((
+                x.0 .0,
+                x.1 .0.get(x.0 .0).map(String::as_str),
+                x.0 .1,
+                x.1 .1,
+            ))
+        });
+}
+
+// The operation whose id starts with x
+pub fn find_operation(x: &str) -> Result<Operation, String> {
+    let x: (&str, Vec<Operation>) = (
+        x,
+        get_operations()
+            .into_iter()
+            .zip(std::iter::repeat(x))
+            .filter(|x| !x.1.is_empty() && x.0 .0.starts_with(x.1))
+            .map(|x| x.0)
+            .collect(),
+    );
+
+    match x.1.len() {
+        0 => Err(format!("Unknown operation '{}'", x.0)),
+        1 => Ok(x.1[0].clone()),
+        _ => Err(format!("Operation id '{}' is ambiguous", x.0)),
+    }
+}
+
+// Restores the repository to how it was right after operation x, which is recorded as an operation
+// itself so that it can be undone
+pub fn restore_operation(x: &Operation) -> Result<(), String> {
+    let x: (&Operation, SnapshotFiles) = (x, read_snapshot_files(&x.2)?);
+
+    record((&format!("restore operation {}", x.0 .0), || {
+        restore_snapshot((
+            &x.1,
+            &format!("op restore: {}", helpers::abbreviate(&x.0 .0)),
+        ))
+    }));
+    Ok(())
+}
+
+// Restores the repository to how it was before the latest operation that hasn't been undone yet,
+// so that undoing repeatedly keeps going further back. Returns the operation that was undone, if
+// there was one.
+pub fn undo() -> Result<Option<Operation>, String> {
+    // Undo operations are skipped along with the operations they undid
+    let x: RefCell<BTreeSet<String>> = RefCell::new(BTreeSet::new());
+    let x: Option<Operation> = get_operations()
+        .into_iter()
+        .rev()
+        .zip(std::iter::repeat(&x))
+        .find(|x| {
+            if let Some(x) = x.0 .4.strip_prefix("undo operation ").zip(Some(x.1)) {
+                x.1.borrow_mut().insert(x.0.to_string());
+                return false;
+            }
+            !x.1.borrow().contains(&x.0 .0)
+        })
+        .map(|x| x.0);
+
+    let x: Operation = match x {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let x: (Operation, Snapshot, SnapshotFiles) =
+        (x.clone(), take_snapshot(), read_snapshot_files(&x.1)?);
+    restore_snapshot((&x.2, &format!("undo: {}", x.0 .4)));
+    append_operation((
+        &x.1,
+        &take_snapshot(),
+        &format!("undo operation {}", x.0 .0),
+    ));
+
+    Ok(Some(x.0))
+}
@@ -0,0 +1,301 @@
+use flate2::read::ZlibEncoder;
+use flate2::Compression;
+use std::env;
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// A new empty directory named after test x, so that tests running in parallel don't share one
+fn create_directory(x: &str) -> PathBuf {
+    let x: PathBuf = env::temp_dir().join(format!("grit-cli-{}-{}", x, std::process::id()));
+    let _ = fs::remove_dir_all(&x);
+    fs::create_dir_all(&x).unwrap();
+    x
+}
+
+// Runs `grit <x.1>...` in directory x.0 with only the environment variables x.2 set (and HOME
+// pointing at the directory, so that no global config is picked up)
+fn grit(x: (&PathBuf, &[&str], &[(&str, &str)])) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_grit"))
+        .args(x.1)
+        .current_dir(x.0)
+        .env_clear()
+        .env("HOME", x.0)
+        .envs(x.2.iter().copied())
+        .output()
+        .unwrap()
+}
+
+// The output of `grit <x.1>...` in directory x.0, with x.2 set in the environment
+fn grit_stdout(x: (&PathBuf, &[&str], &[(&str, &str)])) -> String {
+    String::from_utf8_lossy(&grit(x).stdout).to_string()
+}
+
+// The committer's name and email fall back to the author's variables
+#[test]
+fn commit_with_only_author_variables() {
+    let x: (PathBuf, &[(&str, &str)]) = (
+        create_directory("author"),
+        &[("GRIT_AUTHOR_NAME", "A"), ("GRIT_AUTHOR_EMAIL", "a@x")],
+    );
+    grit((&x.0, &["init"], x.1));
+    fs::write(x.0.join("f"), "f\n").unwrap();
+    grit((&x.0, &["add", "f"], x.1));
+
+    assert!(grit((&x.0, &["commit", "-m", "x"], x.1)).status.success());
+    assert_eq!(grit_stdout((&x.0, &["log", "--format=%s"], x.1)), "x\n");
+
+    let _ = fs::remove_dir_all(&x.0);
+}
+
+// Ignored directories and globs that only match ignored files need -f, like ignored files do
+#[test]
+fn add_refuses_ignored_directories_and_globs() {
+    let x: (PathBuf, &[(&str, &str)]) = (create_directory("ignored"), &[]);
+    grit((&x.0, &["init"], x.1));
+    fs::write(x.0.join(".gritignore"), "target/\n*.log\n").unwrap();
+    fs::create_dir_all(x.0.join("target/debug")).unwrap();
+    fs::write(x.0.join("target/debug/x"), "x\n").unwrap();
+    fs::write(x.0.join("a.log"), "a\n").unwrap();
+
+    assert_eq!(
+        grit_stdout((&x.0, &["add", "target"], x.1)),
+        "target is ignored, use -f if you really want to add it.\n"
+    );
+    assert_eq!(
+        grit_stdout((&x.0, &["add", "target/debug"], x.1)),
+        "target/debug is ignored, use -f if you really want to add it.\n"
+    );
+    assert_eq!(
+        grit_stdout((&x.0, &["add", "*.log"], x.1)),
+        "*.log only matches ignored files, use -f if you really want to add them.\n"
+    );
+    assert_eq!(
+        grit_stdout((&x.0, &["status", "-s"], x.1)),
+        "?? .gritignore\n"
+    );
+
+    assert!(grit_stdout((&x.0, &["add", "-f", "target"], x.1)).contains("target/debug/x"));
+
+    let _ = fs::remove_dir_all(&x.0);
+}
+
+// Legacy objects are left in .grit by migrate-objects, even if their contents happen to be zlib
+// data, and are converted by migrate-ids
+#[test]
+fn migrate_objects_leaves_legacy_objects_to_migrate_ids() {
+    let x: (PathBuf, &[(&str, &str)]) = (
+        create_directory("legacy"),
+        &[("GRIT_AUTHOR_NAME", "A"), ("GRIT_AUTHOR_EMAIL", "a@x")],
+    );
+    grit((&x.0, &["init"], x.1));
+    fs::write(x.0.join("f"), "f\n").unwrap();
+    grit((&x.0, &["add", "f"], x.1));
+    grit((&x.0, &["commit", "-m", "x"], x.1));
+
+    let x: (PathBuf, &[(&str, &str)], PathBuf) = (
+        x.0.clone(),
+        x.1,
+        x.0.join(".grit/0123456789abcdef0123456789abcdef01234567"),
+    );
+    fs::write(
+        &x.2,
+        BufReader::new(ZlibEncoder::new(
+            b"no header".as_slice(),
+            Compression::default(),
+        ))
+        .bytes()
+        .collect::<Result<Vec<u8>, std::io::Error>>()
+        .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        grit_stdout((&x.0, &["migrate-objects"], x.1)),
+        "Moved 0 objects into .grit/objects\n\
+         1 objects are in the legacy format, run `grit migrate-ids` to convert them\n"
+    );
+    assert!(x.2.exists());
+
+    assert!(grit_stdout((&x.0, &["migrate-ids"], x.1)).contains("Rewrote 1 object ids"));
+    assert!(!x.2.exists());
+    assert_eq!(grit_stdout((&x.0, &["log", "--format=%s"], x.1)), "x\n");
+
+    let _ = fs::remove_dir_all(&x.0);
+}
+
+// Nothing inside an ignored directory can be re-included, and nested .gritignore files only apply
+// below their own directory
+#[test]
+fn check_ignore_prefers_ignored_parent_directories() {
+    let x: (PathBuf, &[(&str, &str)]) = (create_directory("check-ignore"), &[]);
+    grit((&x.0, &["init"], x.1));
+    fs::write(x.0.join(".gritignore"), "build/\n!build/keep\n*.o\n").unwrap();
+    fs::create_dir_all(x.0.join("build")).unwrap();
+    fs::create_dir_all(x.0.join("src")).unwrap();
+    fs::write(x.0.join("src/.gritignore"), "!main.o\n").unwrap();
+
+    assert_eq!(
+        grit_stdout((
+            &x.0,
+            &["check-ignore", "build/keep", "src/main.o", "src/x.o", "x.c"],
+            x.1
+        )),
+        ".gritignore:1:build/\tbuild/keep\n\
+         src/.gritignore:1:!main.o\tsrc/main.o\n\
+         .gritignore:3:*.o\tsrc/x.o\n"
+    );
+
+    let _ = fs::remove_dir_all(&x.0);
+}
+
+// Every form of revision: `~` follows first parents, `^<n>` picks a parent, `@{n}` reads the
+// reflog, ids can be shortened to 4 characters, and "<rev>:<path>" names a file in a revision
+#[test]
+fn revisions_resolve_suffixes_reflogs_ids_and_paths() {
+    let x: (PathBuf, &[(&str, &str)]) = (
+        create_directory("revisions"),
+        &[("GRIT_AUTHOR_NAME", "A"), ("GRIT_AUTHOR_EMAIL", "a@x")],
+    );
+    grit((&x.0, &["init"], x.1));
+
+    // c1 - c2 - c3 - c4 - merge on main, with s1 on side branching off c3
+    [
+        ("", "f", "c1"),
+        ("", "f", "c2"),
+        ("", "f", "c3"),
+        ("side", "g", "s1"),
+        ("main", "f", "c4"),
+    ]
+    .iter()
+    .zip(std::iter::repeat(&x))
+    .for_each(|x| {
+        match x.0 .0 {
+            "" => (),
+            "side" => assert!(grit((&x.1 .0, &["switch", "-c", "side"], x.1 .1))
+                .status
+                .success()),
+            _ => assert!(grit((&x.1 .0, &["switch", x.0 .0], x.1 .1))
+                .status
+                .success()),
+        }
+        fs::write(x.1 .0.join(x.0 .1), format!("{}\n", x.0 .2)).unwrap();
+        grit((&x.1 .0, &["add", x.0 .1], x.1 .1));
+        assert!(grit((&x.1 .0, &["commit", "-m", x.0 .2], x.1 .1))
+            .status
+            .success());
+    });
+    assert!(grit((&x.0, &["merge", "side"], x.1)).status.success());
+
+    [
+        ("HEAD", "Merge branch 'side'"),
+        ("@", "Merge branch 'side'"),
+        ("HEAD^0", "Merge branch 'side'"),
+        ("HEAD^", "c4"),
+        ("HEAD^1", "c4"),
+        ("HEAD^2", "s1"),
+        ("HEAD^2~", "c3"),
+        ("HEAD~2", "c3"),
+        ("HEAD~^", "c3"),
+        ("main~4", "c1"),
+        ("side~2^", "c1"),
+        (
+            "HEAD~5",
+            "Revision 'HEAD~5' goes past the history it refers to",
+        ),
+        (
+            "HEAD^3",
+            "Revision 'HEAD^3' goes past the history it refers to",
+        ),
+        ("HEAD~x", "Invalid revision 'HEAD~x'"),
+        ("main@{0}", "Merge branch 'side'"),
+        ("main@{1}", "c4"),
+        ("@{2}", "c3"),
+        ("main@{1}~1", "c3"),
+        ("side@{0}", "s1"),
+        ("@{9}", "Reflog for 'refs/heads/main' only has 5 entries"),
+        ("nope", "Unknown revision 'nope'"),
+    ]
+    .iter()
+    .zip(std::iter::repeat(&x))
+    .for_each(|x| {
+        assert_eq!(
+            grit_stdout((&x.1 .0, &["log", "-n", "1", "--format=%s", x.0 .0], x.1 .1)),
+            format!("{}\n", x.0 .1),
+            "{}",
+            x.0 .0
+        )
+    });
+
+    // Full and abbreviated ids of the first commit
+    let x: (PathBuf, &[(&str, &str)], String) = (
+        x.0.clone(),
+        x.1,
+        grit_stdout((&x.0, &["log", "-n", "1", "--format=%H", "HEAD~4"], x.1))
+            .trim()
+            .to_string(),
+    );
+    [&x.2[..], &x.2[..4], &x.2[..10]]
+        .iter()
+        .zip(std::iter::repeat(&x))
+        .for_each(|x| {
+            assert_eq!(
+                grit_stdout((&x.1 .0, &["log", "-n", "1", "--format=%s", x.0], x.1 .1)),
+                "c1\n",
+                "{}",
+                x.0
+            )
+        });
+    assert_eq!(
+        grit_stdout((&x.0, &["log", "-n", "1", "--format=%s", &x.2[..3]], x.1)),
+        format!("Unknown revision '{}'\n", &x.2[..3])
+    );
+
+    assert_eq!(grit_stdout((&x.0, &["show", "HEAD~2:f"], x.1)), "c3\n");
+    assert_eq!(grit_stdout((&x.0, &["show", "HEAD^2:g"], x.1)), "s1\n");
+    assert_eq!(grit_stdout((&x.0, &["show", ":f"], x.1)), "c4\n");
+    assert_eq!(
+        grit_stdout((&x.0, &["show", "HEAD~4:g"], x.1)),
+        "Path 'HEAD~4:g' does not exist\n"
+    );
+
+    let _ = fs::remove_dir_all(&x.0);
+}
+
+// An empty path names the root tree, which exists even when the commit has no files
+#[test]
+fn show_lists_the_root_of_an_empty_tree() {
+    let x: (PathBuf, &[(&str, &str)]) = (
+        create_directory("show-root"),
+        &[("GRIT_AUTHOR_NAME", "A"), ("GRIT_AUTHOR_EMAIL", "a@x")],
+    );
+    grit((&x.0, &["init"], x.1));
+    fs::create_dir_all(x.0.join("d")).unwrap();
+    fs::write(x.0.join("d/f"), "f\n").unwrap();
+    grit((&x.0, &["add", "d/f"], x.1));
+    grit((&x.0, &["commit", "-m", "x"], x.1));
+    grit((&x.0, &["rm", "d/f"], x.1));
+    grit((&x.0, &["commit", "-m", "y"], x.1));
+
+    assert_eq!(
+        grit_stdout((&x.0, &["show", "HEAD:"], x.1)),
+        "tree HEAD:\n\n"
+    );
+    assert_eq!(grit_stdout((&x.0, &["show", ":"], x.1)), "tree :\n\n");
+    assert_eq!(
+        grit_stdout((&x.0, &["show", "HEAD~1:"], x.1)),
+        "tree HEAD~1:\n\nd/\n"
+    );
+    assert_eq!(
+        grit_stdout((&x.0, &["show", "HEAD~1:d"], x.1)),
+        "tree HEAD~1:d\n\nf\n"
+    );
+    assert_eq!(grit_stdout((&x.0, &["show", "HEAD~1:d/f"], x.1)), "f\n");
+    assert_eq!(
+        grit_stdout((&x.0, &["show", "HEAD:d"], x.1)),
+        "Path 'HEAD:d' does not exist\n"
+    );
+
+    let _ = fs::remove_dir_all(&x.0);
+}